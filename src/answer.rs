@@ -0,0 +1,422 @@
+use std::{collections::HashMap, env};
+
+use scraper::Html;
+
+use crate::{error::ErpError, secret::Secret, utils::ErpCreds, utils::Res};
+
+/// Environment variable holding a JSON object of security questions to answers
+pub const ANSWERS_ENV_VAR: &str = "ERP_ANSWERS";
+/// Prefix of the environment variables holding indexed questions (`ERP_Q1`, `ERP_Q2`, ...)
+pub const QUESTION_ENV_VAR_PREFIX: &str = "ERP_Q";
+/// Prefix of the environment variables holding indexed answers (`ERP_A1`, `ERP_A2`, ...)
+pub const ANSWER_ENV_VAR_PREFIX: &str = "ERP_A";
+
+/// A source of answers to ERP's security questions
+pub trait AnswerProvider {
+    /// Returns the answer to `question`, the exact security question asked by ERP for the account in `credentials`
+    fn answer(
+        &self,
+        question: &str,
+        credentials: &ErpCreds,
+    ) -> impl Future<Output = Res<Secret<String>>>;
+}
+
+/// Suggested minimum similarity of a fuzzy match, see [`QuestionMatcher::fuzzy_threshold`]
+pub const DEFAULT_FUZZY_THRESHOLD: f64 = 0.85;
+
+/// How a stored question matched the question asked by ERP
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchKind {
+    /// The questions are identical
+    Exact,
+    /// The questions are identical after normalization, see [`normalize_question`]
+    Normalized,
+    /// The normalized questions are similar, with this similarity between 0 and 1
+    Fuzzy(f64),
+}
+
+/// A stored question matched to the question asked by ERP
+#[derive(Debug, Clone)]
+pub struct QuestionMatch<'a> {
+    /// The question as stored in the answer map
+    pub question: &'a str,
+    pub kind: MatchKind,
+}
+
+/// Finds the stored question matching the security question asked by ERP: exactly, then after normalizing both
+/// (see [`normalize_question`]), then by similarity if enabled.
+/// Refuses to answer if several stored questions match at the same step.
+#[derive(Debug, Clone)]
+pub struct QuestionMatcher {
+    fuzzy_threshold: Option<f64>,
+    report: bool,
+}
+
+impl QuestionMatcher {
+    /// Matches exactly and after normalization only, and reports inexact matches
+    pub fn new() -> Self {
+        Self {
+            fuzzy_threshold: None,
+            report: true,
+        }
+    }
+
+    /// Minimum similarity (0 to 1, by edit distance of the normalized questions) of a fuzzy match,
+    /// or `None` to disable fuzzy matching. Disabled by default.
+    ///
+    /// Different questions can be very similar ("mother's maiden name" and "father's maiden name"),
+    /// so a fuzzy match may answer the wrong question. [`DEFAULT_FUZZY_THRESHOLD`] is a reasonable value.
+    pub fn fuzzy_threshold(mut self, fuzzy_threshold: Option<f64>) -> Self {
+        self.fuzzy_threshold = fuzzy_threshold;
+        self
+    }
+
+    /// Prints which stored question was used when it did not match exactly. Enabled by default.
+    pub fn report(mut self, report: bool) -> Self {
+        self.report = report;
+        self
+    }
+
+    /// Finds the stored question matching `question` and its answer.
+    /// Fails with [`ErpError::UnknownQuestion`], listing the stored questions, if none matches,
+    /// and with [`ErpError::AmbiguousQuestion`] if several match.
+    pub fn find<'a, V>(
+        &self,
+        answer_map: &'a HashMap<String, V>,
+        question: &str,
+    ) -> Res<(QuestionMatch<'a>, &'a V)> {
+        if let Some((stored, answer)) = answer_map.get_key_value(question) {
+            return Ok((
+                QuestionMatch {
+                    question: stored,
+                    kind: MatchKind::Exact,
+                },
+                answer,
+            ));
+        }
+
+        let normalized = normalize_question(question);
+        let normalized_map: Vec<_> = answer_map
+            .iter()
+            .map(|(stored, answer)| (stored, answer, normalize_question(stored)))
+            .collect();
+
+        let mut matches: Vec<(QuestionMatch, &V)> = normalized_map
+            .iter()
+            .filter(|(_, _, stored_normalized)| *stored_normalized == normalized)
+            .map(|(stored, answer, _)| {
+                let kind = MatchKind::Normalized;
+                (
+                    QuestionMatch {
+                        question: stored,
+                        kind,
+                    },
+                    *answer,
+                )
+            })
+            .collect();
+
+        if let (true, Some(threshold)) = (matches.is_empty(), self.fuzzy_threshold) {
+            matches = normalized_map
+                .iter()
+                .filter_map(|(stored, answer, stored_normalized)| {
+                    let similarity = similarity(stored_normalized, &normalized);
+                    let kind = MatchKind::Fuzzy(similarity);
+                    (similarity >= threshold).then_some((
+                        QuestionMatch {
+                            question: stored,
+                            kind,
+                        },
+                        *answer,
+                    ))
+                })
+                .collect();
+        }
+
+        if matches.len() > 1 {
+            let mut candidates: Vec<String> = matches
+                .iter()
+                .map(|(question_match, _)| question_match.question.to_owned())
+                .collect();
+            candidates.sort();
+
+            return Err(ErpError::AmbiguousQuestion {
+                question: question.into(),
+                candidates,
+            });
+        }
+
+        let Some((question_match, answer)) = matches.pop() else {
+            let mut known: Vec<_> = answer_map.keys().cloned().collect();
+            known.sort();
+
+            return Err(ErpError::UnknownQuestion {
+                question: question.into(),
+                known,
+            });
+        };
+
+        if self.report {
+            println!(
+                "Matched security question {question:?} to stored question {:?}.",
+                question_match.question
+            );
+        }
+
+        Ok((question_match, answer))
+    }
+
+    /// Looks up the answer to `question` in an answer map
+    fn lookup<V: Clone + Into<Secret<String>>>(
+        &self,
+        answer_map: &HashMap<String, V>,
+        question: &str,
+    ) -> Res<Secret<String>> {
+        self.find(answer_map, question)
+            .map(|(_, answer)| answer.clone().into())
+    }
+}
+
+impl Default for QuestionMatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Answers from the credentials' answer map, matching the questions with this matcher
+impl AnswerProvider for QuestionMatcher {
+    async fn answer(&self, question: &str, credentials: &ErpCreds) -> Res<Secret<String>> {
+        let answer_map = credentials
+            .answer_map
+            .as_ref()
+            .ok_or(ErpError::Missing("security question answers"))?;
+
+        self.lookup(answer_map, question)
+    }
+}
+
+/// Normalizes a security question for matching: decodes HTML entities, lowercases it,
+/// replaces punctuation with spaces and collapses whitespace
+pub fn normalize_question(question: &str) -> String {
+    let decoded: String = Html::parse_fragment(question)
+        .root_element()
+        .text()
+        .collect();
+
+    decoded
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Similarity of two strings between 0 and 1, from their edit distance
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let max_len = a.len().max(b.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+
+    // Levenshtein distance, keeping one row of the table
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+
+    1.0 - row[b.len()] as f64 / max_len as f64
+}
+
+/// Answers from the credentials' answer map, matching the questions with the default [`QuestionMatcher`]
+pub struct StoredAnswers;
+
+impl AnswerProvider for StoredAnswers {
+    async fn answer(&self, question: &str, credentials: &ErpCreds) -> Res<Secret<String>> {
+        QuestionMatcher::default()
+            .answer(question, credentials)
+            .await
+    }
+}
+
+/// Answers from a map of security questions to answers
+impl AnswerProvider for HashMap<String, String> {
+    async fn answer(&self, question: &str, _credentials: &ErpCreds) -> Res<Secret<String>> {
+        QuestionMatcher::default().lookup(self, question)
+    }
+}
+
+/// Answers from a map of security questions to secret answers
+impl AnswerProvider for HashMap<String, Secret<String>> {
+    async fn answer(&self, question: &str, _credentials: &ErpCreds) -> Res<Secret<String>> {
+        QuestionMatcher::default().lookup(self, question)
+    }
+}
+
+/// Asks the user for the answer on the terminal
+pub struct PromptAnswer;
+
+impl AnswerProvider for PromptAnswer {
+    async fn answer(&self, question: &str, _credentials: &ErpCreds) -> Res<Secret<String>> {
+        Ok(rpassword::prompt_password(format!("{question}: "))?.into())
+    }
+}
+
+/// Reads the security questions and answers from the environment when asked.
+/// See [`answers_from_env`] for the variables used.
+pub struct EnvAnswers;
+
+impl AnswerProvider for EnvAnswers {
+    async fn answer(&self, question: &str, _credentials: &ErpCreds) -> Res<Secret<String>> {
+        QuestionMatcher::default().lookup(&answers_from_env()?, question)
+    }
+}
+
+/// Reads a map of security questions to answers from the environment.
+///
+/// The map is read from `ERP_ANSWERS` as a JSON object, and from indexed `ERP_Q1`/`ERP_A1`, `ERP_Q2`/`ERP_A2`, ...
+/// pairs, stopping at the first missing question. Indexed pairs take precedence over `ERP_ANSWERS`.
+pub fn answers_from_env() -> Res<HashMap<String, Secret<String>>> {
+    let mut answer_map: HashMap<String, Secret<String>> = match env::var(ANSWERS_ENV_VAR) {
+        Ok(answers) => serde_json::from_str(&answers)?,
+        Err(_) => HashMap::new(),
+    };
+
+    for i in 1.. {
+        let Ok(question) = env::var(format!("{QUESTION_ENV_VAR_PREFIX}{i}")) else {
+            break;
+        };
+        let answer = env::var(format!("{ANSWER_ENV_VAR_PREFIX}{i}"))
+            .map_err(|_| ErpError::Missing("answer for an indexed security question"))?;
+
+        answer_map.insert(question, answer.into());
+    }
+
+    Ok(answer_map)
+}
+
+/// Answers with an async callback, called with the security question
+pub struct AnswerFn<F>(pub F);
+
+impl<F, Fut> AnswerProvider for AnswerFn<F>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Res<String>>,
+{
+    async fn answer(&self, question: &str, _credentials: &ErpCreds) -> Res<Secret<String>> {
+        (self.0)(question.to_owned()).await.map(Secret::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer_map(questions: &[&str]) -> HashMap<String, String> {
+        questions
+            .iter()
+            .map(|question| (question.to_string(), format!("answer to {question}")))
+            .collect()
+    }
+
+    const MOTHER: &str = "What is your mother's maiden name?";
+    const FATHER: &str = "What is your father's maiden name?";
+
+    #[test]
+    fn normalizes_questions() {
+        assert_eq!(
+            normalize_question("  What is your  mother&#39;s MAIDEN name? "),
+            "what is your mother s maiden name"
+        );
+        assert_eq!(
+            normalize_question("Name of your first school?"),
+            normalize_question("name of your first school")
+        );
+        assert_eq!(normalize_question("&amp;...?"), "");
+    }
+
+    #[test]
+    fn measures_similarity() {
+        assert_eq!(similarity("maiden name", "maiden name"), 1.0);
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("abc", ""), 0.0);
+        assert_eq!(similarity("abcd", "abce"), 0.75);
+        assert!(similarity("kitten", "sitting") < similarity("kitten", "mitten"));
+    }
+
+    #[test]
+    fn matches_exactly_and_normalized() {
+        let answers = answer_map(&[MOTHER, "Name of your first school?"]);
+        let matcher = QuestionMatcher::new().report(false);
+
+        let (question_match, answer) = matcher.find(&answers, MOTHER).unwrap();
+        assert_eq!(question_match.kind, MatchKind::Exact);
+        assert_eq!(answer, &answers[MOTHER]);
+
+        let (question_match, _) = matcher.find(&answers, "name of your FIRST school").unwrap();
+        assert_eq!(question_match.kind, MatchKind::Normalized);
+        assert_eq!(question_match.question, "Name of your first school?");
+    }
+
+    #[test]
+    fn refuses_near_miss_by_default() {
+        let answers = answer_map(&[MOTHER]);
+        let result = QuestionMatcher::new().report(false).find(&answers, FATHER);
+
+        assert!(matches!(
+            result,
+            Err(ErpError::UnknownQuestion { known, .. }) if known == [MOTHER]
+        ));
+    }
+
+    #[test]
+    fn matches_fuzzily_when_enabled() {
+        let answers = answer_map(&[MOTHER, "Name of your first school?"]);
+        let matcher = QuestionMatcher::new()
+            .fuzzy_threshold(Some(DEFAULT_FUZZY_THRESHOLD))
+            .report(false);
+
+        let (question_match, _) = matcher
+            .find(&answers, "What is your mothers maiden name")
+            .unwrap();
+        assert_eq!(question_match.question, MOTHER);
+        assert!(matches!(question_match.kind, MatchKind::Fuzzy(similarity) if similarity < 1.0));
+    }
+
+    #[test]
+    fn refuses_ambiguous_fuzzy_match() {
+        let answers = answer_map(&[FATHER, MOTHER]);
+        let matcher = QuestionMatcher::new()
+            .fuzzy_threshold(Some(DEFAULT_FUZZY_THRESHOLD))
+            .report(false);
+        let result = matcher.find(&answers, "What is your brother's maiden name?");
+
+        assert!(matches!(
+            result,
+            Err(ErpError::AmbiguousQuestion { candidates, .. }) if candidates == [FATHER, MOTHER]
+        ));
+    }
+
+    #[test]
+    fn refuses_ambiguous_normalized_match() {
+        let answers = answer_map(&["Favourite colour?", "favourite colour"]);
+        let result = QuestionMatcher::new()
+            .report(false)
+            .find(&answers, "FAVOURITE COLOUR");
+
+        assert!(matches!(result, Err(ErpError::AmbiguousQuestion { .. })));
+    }
+}
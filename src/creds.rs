@@ -0,0 +1,138 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::utils::{ErpCreds, Res};
+
+/// Environment variable holding the roll number
+pub const ROLL_NUMBER_ENV_VAR: &str = "ERP_ROLL_NUMBER";
+/// Environment variable holding the ERP password
+pub const PASSWORD_ENV_VAR: &str = "ERP_PASSWORD";
+
+/// A source of ERP credentials
+pub trait CredentialSource {
+    /// Loads the credentials
+    fn credentials(&self) -> impl Future<Output = Res<ErpCreds>>;
+}
+
+impl CredentialSource for ErpCreds {
+    async fn credentials(&self) -> Res<ErpCreds> {
+        Ok(self.clone())
+    }
+}
+
+/// Reads the credentials from a file (typically erpcreds.json)
+pub struct CredsFile(pub PathBuf);
+
+impl CredentialSource for CredsFile {
+    async fn credentials(&self) -> Res<ErpCreds> {
+        ErpCreds::from_file(&self.0)
+    }
+}
+
+/// Reads the credentials from the environment. See [`ErpCreds::from_env`] for the variables used.
+pub struct EnvCredentials;
+
+impl CredentialSource for EnvCredentials {
+    async fn credentials(&self) -> Res<ErpCreds> {
+        ErpCreds::from_env()
+    }
+}
+
+/// Asks the user for the roll number and password on the terminal
+pub struct PromptCredentials;
+
+impl CredentialSource for PromptCredentials {
+    async fn credentials(&self) -> Res<ErpCreds> {
+        Ok(ErpCreds {
+            roll_number: Some(prompt_roll_number()?),
+            password: Some(rpassword::prompt_password("Enter password: ")?.into()),
+            answer_map: None,
+        })
+    }
+}
+
+fn prompt_roll_number() -> Res<String> {
+    let mut rollno = String::new();
+
+    print!("Enter roll number: ");
+    io::stdout().flush()?;
+    io::stdin().read_line(&mut rollno)?;
+
+    Ok(rollno.trim().to_string())
+}
+
+/// Merges credentials from several sources. In order of precedence:
+/// 1. the environment (see [`ErpCreds::from_env`]), if enabled
+/// 2. the credentials file, if set and it exists. It is not read if the environment has all the credentials.
+/// 3. the terminal, asked for the roll number and password if still missing, if enabled
+///
+/// The security question answers of the environment and the file are merged, the environment's taking precedence.
+pub struct LayeredCredentials {
+    env: bool,
+    file: Option<PathBuf>,
+    prompt: bool,
+}
+
+impl LayeredCredentials {
+    /// Reads the environment and asks on the terminal, without a credentials file
+    pub fn new() -> Self {
+        Self {
+            env: true,
+            file: None,
+            prompt: true,
+        }
+    }
+
+    /// Reads the environment. Enabled by default.
+    pub fn env(mut self, env: bool) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Reads this credentials file (plaintext or encrypted) if it exists
+    pub fn file<P: Into<PathBuf>>(mut self, file: P) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Asks for the missing roll number and password on the terminal. Enabled by default.
+    pub fn prompt(mut self, prompt: bool) -> Self {
+        self.prompt = prompt;
+        self
+    }
+}
+
+impl Default for LayeredCredentials {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CredentialSource for LayeredCredentials {
+    async fn credentials(&self) -> Res<ErpCreds> {
+        let mut credentials = if self.env {
+            ErpCreds::from_env()?
+        } else {
+            ErpCreds::default()
+        };
+
+        if let Some(file) = self.file.as_ref().filter(|file| file.exists())
+            && !credentials.is_complete()
+        {
+            credentials = credentials.merge(ErpCreds::from_file(file)?);
+        }
+
+        if self.prompt {
+            if credentials.roll_number.is_none() {
+                credentials.roll_number = Some(prompt_roll_number()?);
+            }
+            if credentials.password.is_none() {
+                credentials.password = Some(rpassword::prompt_password("Enter password: ")?.into());
+            }
+        }
+
+        Ok(credentials)
+    }
+}
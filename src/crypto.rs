@@ -0,0 +1,333 @@
+use std::{env, fs, path::PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{error::ErpError, secret::Secret, utils::Res};
+
+/// Identifies a file as encrypted by this crate
+pub const ENCRYPTED_FILE_FORMAT: &str = "iitkgp-erp-login/encrypted";
+/// Latest version of the encrypted file format
+pub const ENCRYPTED_FILE_VERSION: u32 = 1;
+/// Environment variable holding the passphrase, used instead of prompting for it
+pub const PASSPHRASE_ENV_VAR: &str = "ERP_PASSPHRASE";
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Where the encryption key comes from
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    /// A passphrase, stretched into a key with Argon2id
+    Passphrase(Secret<String>),
+    /// A file of random bytes (e.g. from `head -c 32 /dev/urandom`), hashed into a key with SHA-256
+    KeyFile(PathBuf),
+}
+
+impl KeySource {
+    /// Reads the passphrase with [`read_passphrase`]
+    pub fn passphrase(prompt: &str) -> Res<Self> {
+        Ok(Self::Passphrase(read_passphrase(prompt)?.into()))
+    }
+}
+
+/// Contents of a file encrypted with ChaCha20-Poly1305, stored as JSON.
+/// The key is derived from a passphrase with Argon2id, or from a key file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Encrypted {
+    format: String,
+    version: u32,
+    kdf: Kdf,
+    /// Base64 nonce
+    nonce: String,
+    /// Base64 ciphertext, including the authentication tag
+    ciphertext: String,
+}
+
+/// Key derivation function and its parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+enum Kdf {
+    Argon2id {
+        /// Base64 salt
+        salt: String,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    /// SHA-256 of the key file
+    KeyFile,
+}
+
+impl Kdf {
+    /// Argon2id with the recommended parameters and a random salt for passphrases, SHA-256 for key files
+    fn new(key_source: &KeySource) -> Self {
+        match key_source {
+            KeySource::Passphrase(_) => {
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+
+                Self::Argon2id {
+                    salt: BASE64.encode(salt),
+                    m_cost: Params::DEFAULT_M_COST,
+                    t_cost: Params::DEFAULT_T_COST,
+                    p_cost: Params::DEFAULT_P_COST,
+                }
+            }
+            KeySource::KeyFile(_) => Self::KeyFile,
+        }
+    }
+
+    fn derive_key(&self, key_source: &KeySource) -> Res<Key> {
+        match (self, key_source) {
+            (
+                Self::Argon2id {
+                    salt,
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                },
+                KeySource::Passphrase(passphrase),
+            ) => {
+                let salt = decode(salt, "salt")?;
+                let params =
+                    Params::new(*m_cost, *t_cost, *p_cost, Some(KEY_LEN)).map_err(|err| {
+                        ErpError::Encryption(format!("invalid Argon2 parameters: {err}"))
+                    })?;
+
+                let mut key = Key::default();
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.expose().as_bytes(), &salt, &mut key)
+                    .map_err(|err| ErpError::Encryption(format!("key derivation failed: {err}")))?;
+
+                Ok(key)
+            }
+            (Self::KeyFile, KeySource::KeyFile(key_file)) => {
+                let contents = fs::read(key_file)?;
+                if contents.is_empty() {
+                    return Err(ErpError::Encryption(format!(
+                        "key file {} is empty",
+                        key_file.display()
+                    )));
+                }
+
+                Ok(Sha256::digest(contents))
+            }
+            (Self::Argon2id { .. }, KeySource::KeyFile(_)) => Err(ErpError::Encryption(
+                "the file is encrypted with a passphrase, not a key file".into(),
+            )),
+            (Self::KeyFile, KeySource::Passphrase(_)) => Err(ErpError::Encryption(
+                "the file is encrypted with a key file, not a passphrase".into(),
+            )),
+        }
+    }
+}
+
+fn decode(value: &str, name: &str) -> Res<Vec<u8>> {
+    BASE64
+        .decode(value)
+        .map_err(|err| ErpError::Encryption(format!("invalid {name}: {err}")))
+}
+
+impl Encrypted {
+    /// Encrypts `plaintext` with a key derived from `key_source`
+    pub fn encrypt(plaintext: &[u8], key_source: &KeySource) -> Res<Self> {
+        let kdf = Kdf::new(key_source);
+        let cipher = ChaCha20Poly1305::new(&kdf.derive_key(key_source)?);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| ErpError::Encryption("encryption failed".into()))?;
+
+        Ok(Self {
+            format: ENCRYPTED_FILE_FORMAT.into(),
+            version: ENCRYPTED_FILE_VERSION,
+            kdf,
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    /// Decrypts the contents with a key derived from `key_source`
+    pub fn decrypt(&self, key_source: &KeySource) -> Res<Vec<u8>> {
+        let nonce = decode(&self.nonce, "nonce")?;
+        if nonce.len() != NONCE_LEN {
+            return Err(ErpError::Encryption("invalid nonce length".into()));
+        }
+        let ciphertext = decode(&self.ciphertext, "ciphertext")?;
+
+        let cipher = ChaCha20Poly1305::new(&self.kdf.derive_key(key_source)?);
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| ErpError::DecryptionFailed)
+    }
+
+    /// Serializes the encrypted contents to JSON
+    pub fn to_json(&self) -> Res<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses encrypted contents, returning `None` if `contents` is not encrypted by this crate
+    pub fn from_contents(contents: &[u8]) -> Res<Option<Self>> {
+        let Ok(value) = serde_json::from_slice::<serde_json::Value>(contents) else {
+            return Ok(None);
+        };
+        if value.get("format").and_then(|format| format.as_str()) != Some(ENCRYPTED_FILE_FORMAT) {
+            return Ok(None);
+        }
+
+        match value.get("version").and_then(|version| version.as_u64()) {
+            Some(version) if version == u64::from(ENCRYPTED_FILE_VERSION) => {}
+            Some(version) => {
+                return Err(ErpError::Encryption(format!(
+                    "unsupported encrypted file version {version}"
+                )));
+            }
+            None => return Err(ErpError::Encryption("encrypted file has no version".into())),
+        }
+
+        serde_json::from_value(value)
+            .map(Some)
+            .map_err(|err| ErpError::Encryption(format!("corrupt encrypted file: {err}")))
+    }
+}
+
+/// Reads the passphrase from `ERP_PASSPHRASE`, or asks the user for it on the terminal
+pub fn read_passphrase(prompt: &str) -> Res<String> {
+    match env::var(PASSPHRASE_ENV_VAR) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => Ok(rpassword::prompt_password(prompt)?),
+    }
+}
+
+/// Reads a new passphrase from `ERP_PASSPHRASE`, or asks the user for it twice on the terminal
+pub fn read_new_passphrase() -> Res<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV_VAR) {
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password("Enter new passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(ErpError::Missing("passphrase"));
+    }
+    if rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        return Err(ErpError::Encryption("passphrases do not match".into()));
+    }
+
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::utils::ErpCreds;
+
+    const PLAINTEXT: &[u8] = br#"{"roll_number":"21CS10001"}"#;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("erp-crypto-{name}-{}", std::process::id()))
+    }
+
+    fn passphrase(passphrase: &str) -> KeySource {
+        KeySource::Passphrase(passphrase.to_owned().into())
+    }
+
+    /// Writes a key file, returning its key source
+    fn key_file(path: &Path) -> KeySource {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        fs::write(path, key).unwrap();
+
+        KeySource::KeyFile(path.to_owned())
+    }
+
+    /// Encrypts and serializes `PLAINTEXT`, then parses and decrypts it with `decrypt_key_source`
+    fn round_trip(encrypt_key_source: &KeySource, decrypt_key_source: &KeySource) -> Res<Vec<u8>> {
+        let contents = Encrypted::encrypt(PLAINTEXT, encrypt_key_source)?.to_json()?;
+
+        Encrypted::from_contents(contents.as_bytes())?
+            .expect("encrypted contents not recognized")
+            .decrypt(decrypt_key_source)
+    }
+
+    #[test]
+    fn round_trips_with_passphrase() {
+        let key_source = passphrase("correct horse");
+        assert_eq!(round_trip(&key_source, &key_source).unwrap(), PLAINTEXT);
+        assert!(matches!(
+            round_trip(&key_source, &passphrase("wrong")),
+            Err(ErpError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn round_trips_with_key_file() {
+        let path = temp_path("key");
+        let key_source = key_file(&path);
+        let result = round_trip(&key_source, &key_source);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(result.unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn rejects_mismatched_key_source() {
+        let path = temp_path("mismatched-key");
+        let key_source = key_file(&path);
+        let result = round_trip(&key_source, &passphrase("correct horse"));
+        let _ = fs::remove_file(&path);
+
+        assert!(matches!(result, Err(ErpError::Encryption(_))));
+    }
+
+    #[test]
+    fn parses_only_encrypted_contents() {
+        let creds = serde_json::to_vec(&ErpCreds {
+            roll_number: Some("21CS10001".into()),
+            ..ErpCreds::default()
+        })
+        .unwrap();
+        assert!(Encrypted::from_contents(&creds).unwrap().is_none());
+        assert!(Encrypted::from_contents(b"not json").unwrap().is_none());
+
+        let unknown_version = format!(
+            r#"{{"format": "{ENCRYPTED_FILE_FORMAT}", "version": {}}}"#,
+            ENCRYPTED_FILE_VERSION + 1
+        );
+        assert!(matches!(
+            Encrypted::from_contents(unknown_version.as_bytes()),
+            Err(ErpError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_to_encrypt_twice() {
+        let (creds_path, key_path) = (temp_path("creds"), temp_path("creds-key"));
+        let key_source = key_file(&key_path);
+        ErpCreds {
+            roll_number: Some("21CS10001".into()),
+            ..ErpCreds::default()
+        }
+        .save_to_file(&creds_path)
+        .unwrap();
+
+        let first = ErpCreds::encrypt_file(&creds_path, &key_source);
+        let second = ErpCreds::encrypt_file(&creds_path, &key_source);
+        let read = ErpCreds::from_file_with_key(&creds_path, &key_source);
+        let _ = fs::remove_file(&creds_path);
+        let _ = fs::remove_file(&key_path);
+
+        first.unwrap();
+        assert!(matches!(second, Err(ErpError::Encryption(_))));
+        assert_eq!(read.unwrap().roll_number.as_deref(), Some("21CS10001"));
+    }
+}
@@ -6,6 +6,62 @@ pub mod endpoints {
     pub const SECRET_QUESTION_URL: &str =
         "https://erp.iitkgp.ac.in/SSOAdministration/getSecurityQues.htm";
     pub const OTP_URL: &str = "https://erp.iitkgp.ac.in/SSOAdministration/getEmilOTP.htm"; // blame ERP for the typo
+
+    /// Paths of the endpoints relative to the base URL
+    pub mod paths {
+        pub const HOMEPAGE: &str = "/IIT_ERP3/";
+        pub const WELCOMEPAGE: &str = "/IIT_ERP3/welcome.jsp";
+        pub const LOGIN: &str = "/SSOAdministration/auth.htm";
+        pub const SECRET_QUESTION: &str = "/SSOAdministration/getSecurityQues.htm";
+        pub const OTP: &str = "/SSOAdministration/getEmilOTP.htm";
+    }
+}
+
+/// The set of ERP URLs a [`Session`](crate::Session) talks to.
+/// Defaults to the live ERP; use [`Endpoints::from_base_url`] to target another server, such as a local mock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoints {
+    /// Base URL, used as the domain for the session cookies
+    pub base_url: String,
+    /// ERP homepage, which carries the session token
+    pub homepage: String,
+    /// Welcome page, used to check if a session is alive
+    pub welcome_page: String,
+    /// SSO sign in (auth) endpoint
+    pub auth: String,
+    /// Security question endpoint
+    pub security_question: String,
+    /// Email OTP request endpoint
+    pub otp: String,
+}
+
+impl Endpoints {
+    /// Derives every endpoint from a base URL such as `http://127.0.0.1:8080`
+    pub fn from_base_url(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+
+        Self {
+            base_url: base_url.into(),
+            homepage: format!("{base_url}{}", endpoints::paths::HOMEPAGE),
+            welcome_page: format!("{base_url}{}", endpoints::paths::WELCOMEPAGE),
+            auth: format!("{base_url}{}", endpoints::paths::LOGIN),
+            security_question: format!("{base_url}{}", endpoints::paths::SECRET_QUESTION),
+            otp: format!("{base_url}{}", endpoints::paths::OTP),
+        }
+    }
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            base_url: endpoints::BASE_URL.into(),
+            homepage: endpoints::HOMEPAGE_URL.into(),
+            welcome_page: endpoints::WELCOMEPAGE_URL.into(),
+            auth: endpoints::LOGIN_URL.into(),
+            security_question: endpoints::SECRET_QUESTION_URL.into(),
+            otp: endpoints::OTP_URL.into(),
+        }
+    }
 }
 
 pub(crate) mod responses {
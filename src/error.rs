@@ -0,0 +1,166 @@
+use thiserror::Error;
+
+use crate::verify::RollNumberPart;
+
+/// Errors returned by the ERP login flow and the OTP retrievers
+#[derive(Debug, Error)]
+pub enum ErpError {
+    /// ERP does not recognise the roll number
+    #[error("invalid roll number")]
+    InvalidRollNumber {
+        /// Raw response from ERP
+        response: String,
+    },
+
+    /// The roll number is malformed, checked locally before contacting ERP
+    #[error("invalid roll number {roll_number:?}: {part}")]
+    InvalidRollNumberFormat {
+        /// The roll number
+        roll_number: String,
+        /// The malformed part
+        part: RollNumberPart,
+    },
+
+    /// ERP rejected the password while requesting an OTP
+    #[error("incorrect password")]
+    PasswordMismatch {
+        /// Raw response from ERP
+        response: String,
+    },
+
+    /// ERP rejected the security question's answer while requesting an OTP
+    #[error("incorrect security question answer")]
+    AnswerMismatch {
+        /// Raw response from ERP
+        response: String,
+    },
+
+    /// ERP rejected the email OTP while signing in
+    #[error("OTP mismatch")]
+    OtpMismatch {
+        /// Raw response from ERP
+        response: String,
+    },
+
+    /// ERP responded to an OTP request with an unknown message
+    #[error("error requesting OTP: {response}")]
+    OtpRequestFailed {
+        /// Raw response from ERP
+        response: String,
+    },
+
+    /// The session token could not be found on the ERP homepage
+    #[error("session token not found")]
+    MissingSessionToken,
+
+    /// ERP did not redirect to a URL carrying the SSO token after signing in
+    #[error("SSO token not found in URL {url}")]
+    MissingSsoToken {
+        /// The URL ERP redirected to
+        url: String,
+    },
+
+    /// No stored security question matches the question asked by ERP
+    #[error("no answer for the security question {question:?}, known questions: {known:?}")]
+    UnknownQuestion {
+        /// The question asked by ERP
+        question: String,
+        /// The stored questions
+        known: Vec<String>,
+    },
+
+    /// Several stored security questions match the question asked by ERP equally well, so none is answered
+    #[error("ambiguous security question {question:?}, matching stored questions: {candidates:?}")]
+    AmbiguousQuestion {
+        /// The question asked by ERP
+        question: String,
+        /// The matching stored questions
+        candidates: Vec<String>,
+    },
+
+    /// A credential or session value required for this step is missing
+    #[error("{0} not found")]
+    Missing(&'static str),
+
+    /// The session has not been signed in
+    #[error("session not logged in")]
+    NotLoggedIn,
+
+    /// An authenticated request ended on the login or welcome page: ERP has logged the session out
+    #[error("session logged out: request ended at {url}")]
+    LoggedOut {
+        /// The URL the request ended at
+        url: String,
+    },
+
+    /// An HTTP request to ERP failed
+    #[error("network error: {0}")]
+    Network(#[from] reqwest::Error),
+
+    /// A response, file or value could not be parsed
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    /// A session file is corrupt, of an unsupported version or not a session file at all
+    #[error("invalid session file: {0}")]
+    SessionFile(String),
+
+    /// An encrypted file is corrupt or of an unsupported version, or encrypting it failed
+    #[error("encryption error: {0}")]
+    Encryption(String),
+
+    /// An encrypted file could not be decrypted: the passphrase or key file is wrong or the file was modified
+    #[error("decryption failed: wrong passphrase or key file, or the file was modified")]
+    DecryptionFailed,
+
+    /// A session file is encrypted and was read without a key
+    #[error("session file is encrypted, a passphrase or key file is required")]
+    SessionEncrypted,
+
+    /// A profile does not exist or could not be added, or the profile store is corrupt
+    #[error("profile error: {0}")]
+    Profile(String),
+
+    /// The session's cookie store could not be read or updated
+    #[error("cookie store error: {0}")]
+    CookieStore(String),
+
+    /// The Gmail API returned an error
+    #[error("Gmail error: {0}")]
+    Gmail(#[source] Box<google_gmail1::Error>),
+
+    /// An IMAP server returned an error or rejected the login
+    #[error("IMAP error: {0}")]
+    Imap(String),
+
+    /// Reading from or writing to a file or the terminal failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<serde_json::Error> for ErpError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Parse(err.to_string())
+    }
+}
+
+impl From<chrono::ParseError> for ErpError {
+    fn from(err: chrono::ParseError) -> Self {
+        Self::Parse(err.to_string())
+    }
+}
+
+impl From<async_imap::error::Error> for ErpError {
+    fn from(err: async_imap::error::Error) -> Self {
+        match err {
+            async_imap::error::Error::Io(err) => Self::Io(err),
+            err => Self::Imap(err.to_string()),
+        }
+    }
+}
+
+impl From<google_gmail1::Error> for ErpError {
+    fn from(err: google_gmail1::Error) -> Self {
+        Self::Gmail(Box::new(err))
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use google_gmail1::{
     Gmail,
     api::Scope,
@@ -6,23 +8,36 @@ use google_gmail1::{
     yup_oauth2::{self, InstalledFlowAuthenticator, InstalledFlowReturnMethod},
 };
 
-use crate::{
-    erp,
-    otp::{OTPRetriever, get_otp_from_sub},
-    utils::Res,
-};
+use crate::{erp, error::ErpError, mail, otp::OTPRetriever, paths, utils::Res};
+
+/// File name of the Gmail API client secret
+pub const CLIENT_SECRET_FILE: &str = "gmail_client_secret.json";
+/// File name of the Gmail API token cache
+pub const TOKEN_CACHE_FILE: &str = "gmail_token_cache.json";
 
 pub struct GmailAPIObserver {
     client: Gmail<HttpsConnector<HttpConnector>>,
 }
 
 impl GmailAPIObserver {
+    /// Uses the client secret and token cache at their default paths (see [`paths`])
     pub async fn new() -> Res<Self> {
-        let secret = yup_oauth2::read_application_secret("gmail_client_secret.json").await?;
+        Self::with_paths(paths::gmail_client_secret()?, paths::gmail_token_cache()?).await
+    }
+
+    /// Uses the given client secret and token cache files
+    pub async fn with_paths<P: AsRef<Path>, Q: AsRef<Path>>(
+        client_secret_path: P,
+        token_cache_path: Q,
+    ) -> Res<Self> {
+        let secret = yup_oauth2::read_application_secret(client_secret_path).await?;
+        if let Some(token_cache_dir) = token_cache_path.as_ref().parent() {
+            tokio::fs::create_dir_all(token_cache_dir).await?;
+        }
 
         let auth =
             InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
-                .persist_tokens_to_disk("gmail_token_cache.json") // Saves the token for future use
+                .persist_tokens_to_disk(token_cache_path.as_ref()) // Saves the token for future use
                 .build()
                 .await?;
 
@@ -65,7 +80,12 @@ impl OTPRetriever for GmailAPIObserver {
                 let result = self
                     .client
                     .users()
-                    .messages_get("me", msg.id.as_ref().ok_or("Error: Message id not found.")?)
+                    .messages_get(
+                        "me",
+                        msg.id
+                            .as_ref()
+                            .ok_or(ErpError::Parse("Gmail message id not found".into()))?,
+                    )
                     .add_scope(Scope::Metadata)
                     .format("metadata")
                     .add_metadata_headers("Subject")
@@ -76,42 +96,31 @@ impl OTPRetriever for GmailAPIObserver {
                 let headers = result
                     .1
                     .payload
-                    .ok_or("Error: Message payload not found.")?
+                    .ok_or(ErpError::Parse("Gmail message payload not found".into()))?
                     .headers
-                    .ok_or("Error: Message headers not found.")?;
+                    .ok_or(ErpError::Parse("Gmail message headers not found".into()))?;
 
                 let date = headers
                     .iter()
                     .find(|header| header.name.as_ref().is_some_and(|x| x == "Date"))
-                    .ok_or("Error: Date header not found.")?;
-                let date_timestamp = chrono::DateTime::parse_from_rfc2822(
-                    date.value
-                        .as_ref()
-                        .ok_or("Error: Date header has no value")?,
-                )?
-                .timestamp();
-
-                if date_timestamp < after_timestamp {
-                    Ok(None)
-                } else {
-                    let subject = headers
-                        .iter()
-                        .find(|header| header.name.as_ref().is_some_and(|x| x == "Subject"))
-                        .ok_or("Error: Subject header not found.")?
-                        .value
-                        .as_ref()
-                        .ok_or("Error: Subject header has no value.")?;
-
-                    let otp =
-                        get_otp_from_sub(subject).ok_or("Error: No OTP found in the subject.")?;
+                    .ok_or(ErpError::Parse("Date header not found".into()))?
+                    .value
+                    .as_ref()
+                    .ok_or(ErpError::Parse("Date header has no value".into()))?;
+                let subject = headers
+                    .iter()
+                    .find(|header| header.name.as_ref().is_some_and(|x| x == "Subject"))
+                    .ok_or(ErpError::Parse("Subject header not found".into()))?
+                    .value
+                    .as_ref()
+                    .ok_or(ErpError::Parse("Subject header has no value".into()))?;
 
-                    Ok(Some(otp))
-                }
+                mail::newest_otp([(date.as_str(), subject.as_str())], after_timestamp)
             } else {
                 Ok(None)
             }
         } else {
-            Err("Error: message list empty.".into())
+            Err(ErpError::Parse("Gmail message list empty".into()))
         }
     }
 }
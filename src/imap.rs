@@ -0,0 +1,243 @@
+use std::{fmt::Debug, sync::Arc};
+
+use async_imap::Client;
+use futures_util::TryStreamExt;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{ClientConfig, RootCertStore, crypto::ring, pki_types::ServerName},
+};
+
+use crate::{erp, error::ErpError, mail, otp::OTPRetriever, secret::Secret, utils::Res};
+
+/// Default port of IMAP over TLS
+pub const IMAPS_PORT: u16 = 993;
+/// Default mailbox searched for the OTP email
+pub const DEFAULT_MAILBOX: &str = "INBOX";
+
+/// How the connection to the IMAP server is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImapSecurity {
+    /// IMAP over TLS (IMAPS), verified with the system's root certificates
+    #[default]
+    Tls,
+    /// Unencrypted IMAP, only for local servers such as `MockImap` (with the `mock` feature)
+    Plain,
+}
+
+/// Retrieves the OTP from a mailbox over IMAP, logging in with a username and an app password.
+/// Works with any provider that offers IMAP, including mailboxes ERP mail is forwarded to.
+pub struct ImapObserver {
+    host: String,
+    port: u16,
+    username: String,
+    password: Secret<String>,
+    mailbox: String,
+    security: ImapSecurity,
+}
+
+impl ImapObserver {
+    /// Connects to `host` over IMAPS on port 993
+    pub fn new(
+        host: impl Into<String>,
+        username: impl Into<String>,
+        password: impl Into<Secret<String>>,
+    ) -> Self {
+        Self {
+            host: host.into(),
+            port: IMAPS_PORT,
+            username: username.into(),
+            password: password.into(),
+            mailbox: DEFAULT_MAILBOX.into(),
+            security: ImapSecurity::default(),
+        }
+    }
+
+    /// Port of the IMAP server. Defaults to [`IMAPS_PORT`].
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Mailbox to search for the OTP email. Defaults to [`DEFAULT_MAILBOX`].
+    pub fn mailbox(mut self, mailbox: impl Into<String>) -> Self {
+        self.mailbox = mailbox.into();
+        self
+    }
+
+    /// How the connection is secured. Defaults to [`ImapSecurity::Tls`].
+    pub fn security(mut self, security: ImapSecurity) -> Self {
+        self.security = security;
+        self
+    }
+
+    async fn connect_tls(
+        &self,
+        stream: TcpStream,
+    ) -> Res<tokio_rustls::client::TlsStream<TcpStream>> {
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+        if roots.is_empty() {
+            return Err(ErpError::Imap("no root certificates found".into()));
+        }
+
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|err| ErpError::Imap(err.to_string()))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(self.host.clone())
+            .map_err(|err| ErpError::Imap(format!("invalid host {:?}: {err}", self.host)))?;
+
+        Ok(TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await?)
+    }
+
+    /// Logs in, finds the newest ERP OTP email received after `after_timestamp` and logs out
+    async fn find_otp<T>(&self, stream: T, after_timestamp: i64) -> Res<Option<String>>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Debug + Send,
+    {
+        let mut client = Client::new(stream);
+        client
+            .read_response()
+            .await?
+            .ok_or(ErpError::Imap("server closed the connection".into()))?;
+
+        let mut session = client
+            .login(&self.username, self.password.expose())
+            .await
+            .map_err(|(err, _)| err)?;
+        session.select(&self.mailbox).await?;
+
+        // SINCE only compares dates, in the server's time zone: search from the day before and check the time below
+        let since = chrono::DateTime::from_timestamp(after_timestamp - 24 * 60 * 60, 0).ok_or(
+            ErpError::Parse(format!("invalid timestamp {after_timestamp}")),
+        )?;
+        let uids = session
+            .uid_search(format!(
+                "FROM \"{}\" SUBJECT \"{}\" SINCE {}",
+                erp::email::ERP_EMAIL,
+                erp::email::ERP_OTP_SUBJECT_PREFIX,
+                since.format("%d-%b-%Y")
+            ))
+            .await?;
+
+        let mut mails: Vec<(String, String)> = Vec::new();
+        if !uids.is_empty() {
+            let uid_set = uids
+                .iter()
+                .map(|uid| uid.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            let fetches: Vec<_> = session
+                .uid_fetch(uid_set, "BODY.PEEK[HEADER]")
+                .await?
+                .try_collect()
+                .await?;
+
+            // The server's search is by substring, so the sender and the subject are checked again
+            mails.extend(
+                fetches
+                    .iter()
+                    .filter_map(|fetch| fetch.header())
+                    .filter_map(|header| mail::otp_mail(&String::from_utf8_lossy(header))),
+            );
+        }
+
+        session.logout().await?;
+
+        mail::newest_otp(
+            mails
+                .iter()
+                .map(|(date, subject)| (date.as_str(), subject.as_str())),
+            after_timestamp,
+        )
+    }
+}
+
+impl OTPRetriever for ImapObserver {
+    async fn get_otp(&self, after_timestamp: i64) -> Res<Option<String>> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+
+        match self.security {
+            ImapSecurity::Tls => {
+                let stream = self.connect_tls(stream).await?;
+                self.find_otp(stream, after_timestamp).await
+            }
+            ImapSecurity::Plain => self.find_otp(stream, after_timestamp).await,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockImap;
+
+    const USERNAME: &str = "student@example.com";
+    const PASSWORD: &str = "app-password";
+
+    fn observer(imap: &MockImap, password: &str) -> ImapObserver {
+        ImapObserver::new(imap.host(), USERNAME, password.to_owned())
+            .port(imap.port())
+            .security(ImapSecurity::Plain)
+    }
+
+    #[tokio::test]
+    async fn finds_newest_otp_after_timestamp() {
+        let imap = MockImap::start(USERNAME, PASSWORD).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        imap.deliver_otp("111111", now - 600);
+        imap.deliver_otp("333333", now - 10);
+        imap.deliver_otp("222222", now - 60);
+
+        let observer = observer(&imap, PASSWORD);
+        assert_eq!(
+            observer.get_otp(now - 120).await.unwrap().as_deref(),
+            Some("333333")
+        );
+        assert_eq!(observer.get_otp(now).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn ignores_other_senders_and_subjects() {
+        let imap = MockImap::start(USERNAME, PASSWORD).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        imap.deliver_otp("111111", now - 60);
+        imap.deliver(
+            "Someone <someone@example.com>",
+            &format!("{} 222222", erp::email::ERP_OTP_SUBJECT_PREFIX),
+            now - 30,
+        );
+        imap.deliver(
+            erp::email::ERP_EMAIL,
+            &format!("Re: {} 333333", erp::email::ERP_OTP_SUBJECT_PREFIX),
+            now - 20,
+        );
+        imap.deliver(erp::email::ERP_EMAIL, "Fee payment 444444", now - 10);
+
+        assert_eq!(
+            observer(&imap, PASSWORD)
+                .get_otp(now - 120)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("111111")
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_failed_login() {
+        let imap = MockImap::start(USERNAME, PASSWORD).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        imap.deliver_otp("111111", now);
+
+        let result = observer(&imap, "wrong-password").get_otp(now - 60).await;
+        assert!(matches!(result, Err(ErpError::Imap(_))), "{result:?}");
+    }
+}
@@ -1,8 +1,34 @@
+pub mod answer;
+pub mod creds;
+pub mod crypto;
 pub mod erp;
+mod error;
 pub mod gmail;
+pub mod imap;
+pub mod local_mail;
+mod login;
+mod mail;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod otp;
+pub mod paths;
+pub mod profile;
+mod relogin;
+mod secret;
 mod session;
+mod session_file;
+pub mod setup;
+mod status;
 mod utils;
+pub mod verify;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 
-pub use session::Session;
+pub use error::ErpError;
+pub use login::Login;
+pub use relogin::{ReloginEvent, ReloginSession};
+pub use secret::Secret;
+pub use session::{Fresh, OtpRequested, QuestionFetched, Session, SignedIn};
+pub use session_file::SessionFile;
+pub use status::{Evidence, SessionStatus};
 pub use utils::ErpCreds;
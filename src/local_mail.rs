@@ -0,0 +1,188 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use tokio::{
+    fs::{self, File},
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+};
+
+use crate::{mail, otp::OTPRetriever, utils::Res};
+
+/// Where a [`LocalMailObserver`] looks for mail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailStore {
+    /// A Maildir directory, with the `new` and `cur` subdirectories
+    Maildir(PathBuf),
+    /// An mbox file
+    Mbox(PathBuf),
+}
+
+/// Retrieves the OTP from mail synced to the local disk (e.g. by fetchmail, offlineimap or mbsync),
+/// from a Maildir directory or an mbox file. Needs no credentials.
+pub struct LocalMailObserver {
+    store: MailStore,
+}
+
+impl LocalMailObserver {
+    pub fn new(store: MailStore) -> Self {
+        Self { store }
+    }
+
+    /// Scans the Maildir directory at `path`
+    pub fn maildir<P: Into<PathBuf>>(path: P) -> Self {
+        Self::new(MailStore::Maildir(path.into()))
+    }
+
+    /// Scans the mbox file at `path`
+    pub fn mbox<P: Into<PathBuf>>(path: P) -> Self {
+        Self::new(MailStore::Mbox(path.into()))
+    }
+
+    /// Returns the `Date` and `Subject` headers of the ERP OTP emails in a Maildir directory.
+    /// Files last modified before `after_timestamp` are skipped: they were delivered before the OTP was requested.
+    /// A missing `new` or `cur` subdirectory is treated as empty, as some mail clients create them lazily.
+    async fn maildir_mails(path: &Path, after_timestamp: i64) -> Res<Vec<(String, String)>> {
+        let mut mails = Vec::new();
+
+        for subdir in ["new", "cur"] {
+            let mut entries = match fs::read_dir(path.join(subdir)).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                let modified = metadata
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |modified| modified.as_secs() as i64);
+                if !metadata.is_file() || modified < after_timestamp {
+                    continue;
+                }
+
+                let mut reader = BufReader::new(File::open(entry.path()).await?);
+                let raw = read_header_block(&mut reader).await?;
+                mails.extend(mail::otp_mail(&raw));
+            }
+        }
+
+        Ok(mails)
+    }
+
+    /// Returns the `Date` and `Subject` headers of the ERP OTP emails in an mbox file
+    async fn mbox_mails(path: &Path) -> Res<Vec<(String, String)>> {
+        let mut reader = BufReader::new(File::open(path).await?);
+        let mut mails = Vec::new();
+
+        let mut line = Vec::new();
+        let mut at_message_start = true;
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).await? == 0 {
+                break;
+            }
+
+            // Each message starts with a `From ` separator line, preceded by a blank line except at the start
+            if at_message_start && line.starts_with(b"From ") {
+                let raw = read_header_block(&mut reader).await?;
+                mails.extend(mail::otp_mail(&raw));
+                // The blank line ending the header block may also precede the next message
+                at_message_start = true;
+            } else {
+                at_message_start = line.trim_ascii().is_empty();
+            }
+        }
+
+        Ok(mails)
+    }
+}
+
+/// Reads lines up to and including the blank line ending an email's header block
+async fn read_header_block<R: AsyncBufRead + Unpin>(reader: &mut R) -> Res<String> {
+    let mut raw = Vec::new();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).await? == 0 || line.trim_ascii().is_empty() {
+            break;
+        }
+        raw.extend_from_slice(&line);
+    }
+
+    Ok(String::from_utf8_lossy(&raw).into_owned())
+}
+
+impl OTPRetriever for LocalMailObserver {
+    async fn get_otp(&self, after_timestamp: i64) -> Res<Option<String>> {
+        let mails = match &self.store {
+            MailStore::Maildir(path) => Self::maildir_mails(path, after_timestamp).await?,
+            MailStore::Mbox(path) => Self::mbox_mails(path).await?,
+        };
+
+        mail::newest_otp(
+            mails
+                .iter()
+                .map(|(date, subject)| (date.as_str(), subject.as_str())),
+            after_timestamp,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp;
+
+    #[tokio::test]
+    async fn treats_missing_maildir_subdirectories_as_empty() {
+        let maildir = std::env::temp_dir().join(format!("erp-maildir-{}", std::process::id()));
+        fs::create_dir_all(maildir.join("new")).await.unwrap();
+        fs::write(
+            maildir.join("new").join("1.mail"),
+            format!(
+                "From: {}\r\nSubject: {} 123456\r\nDate: {}\r\n\r\nbody\r\n",
+                erp::email::ERP_EMAIL,
+                erp::email::ERP_OTP_SUBJECT_PREFIX,
+                chrono::Utc::now().to_rfc2822()
+            ),
+        )
+        .await
+        .unwrap();
+
+        let observer = LocalMailObserver::maildir(&maildir);
+        let otp = observer.get_otp(chrono::Utc::now().timestamp() - 60).await;
+        fs::remove_dir_all(&maildir).await.unwrap();
+
+        assert_eq!(otp.unwrap().as_deref(), Some("123456"));
+    }
+
+    #[tokio::test]
+    async fn finds_newest_otp_in_mbox() {
+        let now = chrono::Utc::now();
+        let date = |minutes_ago| (now - chrono::TimeDelta::minutes(minutes_ago)).to_rfc2822();
+        let (erp, prefix) = (erp::email::ERP_EMAIL, erp::email::ERP_OTP_SUBJECT_PREFIX);
+        let mbox = [
+            format!("From {erp} Mon Oct 13 10:00:00 2025\nFrom: {erp}\nSubject: {prefix} 111111\nDate: {}\n\nOld OTP\n", date(120)),
+            format!("From {erp} Mon Oct 13 11:58:00 2025\nFrom: ERP <{erp}>\nSubject: {prefix}\n 222222\nDate: {}\n\nFrom the ERP team\n", date(2)),
+            format!("From {erp} Mon Oct 13 11:55:00 2025\nFrom: {erp}\nSubject: {prefix} 333333\nDate: {}\n\nBody\n", date(5)),
+            format!("From someone@example.com Mon Oct 13 11:59:00 2025\nFrom: someone@example.com\nSubject: {prefix} 444444\nDate: {}\n\nBody\n", date(1)),
+        ]
+        .join("\n");
+
+        let path = std::env::temp_dir().join(format!("erp-mbox-{}", std::process::id()));
+        fs::write(&path, mbox).await.unwrap();
+        let observer = LocalMailObserver::mbox(&path);
+        let newest = observer
+            .get_otp((now - chrono::TimeDelta::minutes(10)).timestamp())
+            .await;
+        let after_all = observer.get_otp(now.timestamp()).await;
+        fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(newest.unwrap().as_deref(), Some("222222"));
+        assert_eq!(after_all.unwrap(), None);
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+
+use reqwest::header::HeaderMap;
+
+use crate::{
+    answer::AnswerProvider,
+    creds::CredentialSource,
+    crypto::KeySource,
+    erp::Endpoints,
+    error::ErpError,
+    otp::OTPRetriever,
+    session::{Session, SignedIn},
+    status::SessionStatus,
+    utils::{ErpCreds, Res},
+};
+
+/// Runs the full ERP login flow: reuses a saved session if it is still alive,
+/// otherwise signs in with the credentials, the security question's answer and the email OTP.
+pub struct Login<C, A, O> {
+    credentials: C,
+    answers: A,
+    otp_retriever: O,
+    /// File to reuse the session from and save it to
+    session_path: Option<PathBuf>,
+    /// Key to encrypt the session file with
+    session_key: Option<KeySource>,
+    endpoints: Endpoints,
+    headers: Option<HeaderMap>,
+    /// Number of times to check for the OTP
+    otp_tries: usize,
+}
+
+impl<C: CredentialSource, A: AnswerProvider, O: OTPRetriever> Login<C, A, O> {
+    pub fn new(credentials: C, answers: A, otp_retriever: O) -> Self {
+        Self {
+            credentials,
+            answers,
+            otp_retriever,
+            session_path: None,
+            session_key: None,
+            endpoints: Endpoints::default(),
+            headers: None,
+            otp_tries: 5,
+        }
+    }
+
+    /// Reuses the session saved in this file if it is alive, and saves the new session to it otherwise
+    pub fn session_path<P: Into<PathBuf>>(mut self, session_path: P) -> Self {
+        self.session_path = Some(session_path.into());
+        self
+    }
+
+    /// Encrypts the session file with the key from `key_source`
+    pub fn session_key(mut self, key_source: KeySource) -> Self {
+        self.session_key = Some(key_source);
+        self
+    }
+
+    /// Talks to the given endpoints instead of the live ERP
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Headers for the session's post requests
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = Some(headers);
+        self
+    }
+
+    /// Number of times to check for the OTP (see [`OTPRetriever::wait_for_otp`]). Defaults to 5.
+    pub fn otp_tries(mut self, otp_tries: usize) -> Self {
+        self.otp_tries = otp_tries;
+        self
+    }
+
+    /// Loads the saved session, if there is one, and checks its status.
+    /// A corrupt or foreign session file is reported and ignored, so that signing in overwrites it;
+    /// an encrypted one that cannot be decrypted is an error.
+    pub async fn saved_session(&self) -> Res<Option<(Session<SignedIn>, SessionStatus)>> {
+        let Some(session_path) = self.session_path.as_ref().filter(|path| path.exists()) else {
+            return Ok(None);
+        };
+
+        let session = self.new_session(ErpCreds::default());
+        let mut session = match session
+            .read_session_with_key(session_path, self.session_key.as_ref())
+            .await
+        {
+            Ok(session) => session,
+            Err(ErpError::NotLoggedIn) => return Ok(None),
+            Err(err @ ErpError::SessionFile(_)) => {
+                println!(
+                    "Ignoring the session file {}: {err}",
+                    session_path.display()
+                );
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+
+        let status = session.verify().await;
+        if status.is_alive() {
+            // Records the verification time, and migrates legacy and unencrypted session files
+            session
+                .save_session_with_key(session_path, self.session_key.as_ref())
+                .await?;
+        }
+
+        Ok(Some((session, status)))
+    }
+
+    /// Runs the login flow, returning the saved session if it is alive and signing in otherwise
+    pub async fn run(&self) -> Res<Session<SignedIn>> {
+        if let Some((session, status)) = self.saved_session().await?
+            && status.is_alive()
+        {
+            return Ok(session);
+        }
+
+        self.signin().await
+    }
+
+    /// Signs in with a new session, ignoring the saved session, and saves it
+    pub async fn signin(&self) -> Res<Session<SignedIn>> {
+        let credentials = self.credentials.credentials().await?;
+        let session = self
+            .new_session(credentials)
+            .get_secret_question(None)
+            .await?;
+
+        let session = session.request_otp(None, &self.answers).await?;
+
+        let otp = self
+            .otp_retriever
+            .wait_for_otp(session.otp_requested_at(), self.otp_tries)
+            .await?
+            .ok_or(ErpError::Missing("email OTP"))?;
+
+        let session = session.signin(otp.into()).await?;
+
+        if let Some(session_path) = &self.session_path {
+            session
+                .save_session_with_key(session_path, self.session_key.as_ref())
+                .await?;
+        }
+
+        Ok(session)
+    }
+
+    fn new_session(&self, credentials: ErpCreds) -> Session {
+        Session::with_endpoints(credentials, self.headers.clone(), self.endpoints.clone())
+    }
+}
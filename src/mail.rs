@@ -0,0 +1,93 @@
+//! Parsing of ERP OTP emails, shared by the OTP retrievers
+
+use crate::{erp, error::ErpError, otp::get_otp_from_sub, utils::Res};
+
+/// Parses the header block of an email into names and values, joining folded lines
+pub(crate) fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    for line in raw.lines() {
+        if line.is_empty() {
+            // End of the header block
+            break;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+
+    headers
+}
+
+/// Finds a header by name, ignoring case
+pub(crate) fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Checks if the `From` and `Subject` headers are those of an ERP OTP email
+pub(crate) fn is_otp_mail(from: &str, subject: &str) -> bool {
+    from.to_ascii_lowercase().contains(erp::email::ERP_EMAIL)
+        && subject.starts_with(erp::email::ERP_OTP_SUBJECT_PREFIX)
+}
+
+/// Returns the `Date` and `Subject` headers if the header block is that of an ERP OTP email
+pub(crate) fn otp_mail(raw: &str) -> Option<(String, String)> {
+    let headers = parse_headers(raw);
+    let header = |name| find_header(&headers, name).unwrap_or_default();
+
+    is_otp_mail(header("From"), header("Subject"))
+        .then(|| (header("Date").to_owned(), header("Subject").to_owned()))
+}
+
+/// Returns the OTP from the newest of the ERP OTP emails, given as their `Date` and `Subject` headers,
+/// received after `after_timestamp`. Emails with a missing or malformed `Date` are skipped.
+pub(crate) fn newest_otp<'a>(
+    mails: impl IntoIterator<Item = (&'a str, &'a str)>,
+    after_timestamp: i64,
+) -> Res<Option<String>> {
+    let mut newest: Option<(i64, &str)> = None;
+    for (date, subject) in mails {
+        let Ok(timestamp) = chrono::DateTime::parse_from_rfc2822(date).map(|date| date.timestamp())
+        else {
+            continue;
+        };
+
+        if timestamp >= after_timestamp && newest.is_none_or(|(newest, _)| timestamp > newest) {
+            newest = Some((timestamp, subject));
+        }
+    }
+
+    newest
+        .map(|(_, subject)| {
+            get_otp_from_sub(subject).ok_or(ErpError::Parse("no OTP found in the subject".into()))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_mails_with_malformed_dates() {
+        let subject = format!("{} 123456", erp::email::ERP_OTP_SUBJECT_PREFIX);
+        let newer = format!("{} 654321", erp::email::ERP_OTP_SUBJECT_PREFIX);
+        let mails = [
+            ("Tue, 14 Oct 2025 10:00:00 +0530", subject.as_str()),
+            ("yesterday", newer.as_str()),
+            ("", newer.as_str()),
+        ];
+
+        assert_eq!(newest_otp(mails, 0).unwrap().as_deref(), Some("123456"));
+        assert_eq!(newest_otp(mails[1..].iter().copied(), 0).unwrap(), None);
+    }
+}
@@ -1,88 +1,364 @@
-use std::{
-    error::Error,
-    io::{self, Write},
-    path,
-    str::FromStr,
+use std::{collections::HashMap, error::Error, fs, path};
+
+use clap::{Parser, Subcommand};
+use iitkgp_erp_login::{
+    ErpCreds, Login,
+    answer::{AnswerProvider, PromptAnswer, StoredAnswers, answers_from_env},
+    creds::{CredentialSource, LayeredCredentials, PromptCredentials},
+    crypto::{self, KeySource},
+    erp::Endpoints,
+    gmail::GmailAPIObserver,
+    otp::{OTPRetriever, PromptRetriever},
+    paths,
+    profile::{Profile, ProfileStore},
+    setup, verify,
 };
 
-use iitkgp_erp_login::{ErpCreds, Session, gmail::GmailAPIObserver, otp::OTPRetriever};
+/// Logs in to IIT KGP ERP and opens the signed in session in the browser
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Profile to use, by name or roll number (defaults to the default profile, if any)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Encrypts the session file with a passphrase (read from ERP_PASSPHRASE if set)
+    #[arg(long)]
+    encrypt_session: bool,
+    /// Encrypts the session file with a key file
+    #[arg(long, value_name = "PATH", conflicts_with = "encrypt_session")]
+    session_key_file: Option<path::PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypts a plaintext credentials file in place with a passphrase (read from ERP_PASSPHRASE if set)
+    EncryptCreds {
+        /// Credentials file to encrypt (defaults to the profile's, or the default credentials file)
+        path: Option<path::PathBuf>,
+        /// Encrypts with a key file instead of a passphrase
+        #[arg(long, value_name = "PATH")]
+        key_file: Option<path::PathBuf>,
+    },
+    /// Discovers the security questions, asks for their answers and the password, and saves a credentials file
+    Setup {
+        /// Credentials file to write (defaults to the profile's, or the default credentials file)
+        path: Option<path::PathBuf>,
+        /// Checks the password and one answer by requesting an OTP (ERP emails it)
+        #[arg(long)]
+        verify: bool,
+        /// Encrypts the credentials file with a passphrase (read from ERP_PASSPHRASE if set)
+        #[arg(long)]
+        encrypt: bool,
+        /// Encrypts the credentials file with a key file
+        #[arg(long, value_name = "PATH", conflicts_with = "encrypt")]
+        key_file: Option<path::PathBuf>,
+        /// Overwrites an existing credentials file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Checks the credentials with ERP without signing in, reporting which one is wrong
+    Verify {
+        /// Credentials file to check (defaults to the profile's, or the default credentials file).
+        /// Credentials in the environment take precedence.
+        path: Option<path::PathBuf>,
+        /// Also checks the password and the answer by requesting an OTP (ERP emails it)
+        #[arg(long)]
+        otp: bool,
+    },
+    /// Manages the profiles of several ERP identities
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// Lists the profiles
+    List,
+    /// Adds a profile
+    Add {
+        name: String,
+        #[arg(long)]
+        roll_number: Option<String>,
+        /// Credentials file to copy into the profile (plaintext or encrypted)
+        #[arg(long, value_name = "PATH")]
+        creds: Option<path::PathBuf>,
+    },
+    /// Removes a profile and its files
+    Remove { name: String },
+    /// Makes a profile the default
+    Default { name: String },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let hub = GmailAPIObserver::new().await?;
+    let cli = Cli::parse();
+    let mut store = ProfileStore::open(paths::profiles_dir()?)?;
+
+    match cli.command {
+        Some(Command::EncryptCreds { path, key_file }) => {
+            let path = creds_path(&store, cli.profile.as_deref(), path)?;
+            let key_source = match key_file {
+                Some(key_file) => KeySource::KeyFile(key_file),
+                None => KeySource::Passphrase(crypto::read_new_passphrase()?.into()),
+            };
+            ErpCreds::encrypt_file(&path, &key_source)?;
+            println!("Encrypted credentials file {}.", path.display());
 
-    let session_file_path = path::PathBuf::from_str(".session")?;
-    let creds_file_path = path::PathBuf::from_str("erpcreds.json")?;
+            Ok(())
+        }
+        Some(Command::Setup {
+            path,
+            verify,
+            encrypt,
+            key_file,
+            force,
+        }) => {
+            let path = creds_path(&store, cli.profile.as_deref(), path)?;
+            if path.exists() && !force {
+                return Err(format!(
+                    "{} already exists, pass --force to overwrite it",
+                    path.display()
+                )
+                .into());
+            }
+            let key_source = match key_file {
+                Some(key_file) => Some(KeySource::KeyFile(key_file)),
+                None if encrypt => {
+                    Some(KeySource::Passphrase(crypto::read_new_passphrase()?.into()))
+                }
+                None => None,
+            };
+
+            enroll(path, verify, key_source).await
+        }
+        Some(Command::Verify { path, otp }) => {
+            let path = creds_path(&store, cli.profile.as_deref(), path)?;
+            let credentials = LayeredCredentials::new()
+                .file(path)
+                .prompt(false)
+                .credentials()
+                .await?;
+
+            let verification =
+                verify::verify(&credentials, &Endpoints::default(), &StoredAnswers, otp).await?;
+            println!("Roll number {} is known to ERP.", verification.roll_number);
+            println!(
+                "The password is set and the security question {:?} has an answer.",
+                verification.question
+            );
+            if verification.otp_requested {
+                println!("ERP accepted the password and the answer, and emailed an OTP.");
+            }
 
-    if session_file_path.exists() {
+            Ok(())
+        }
+        Some(Command::Profile { command }) => manage_profiles(&mut store, command),
+        None => {
+            let session_key = match cli.session_key_file {
+                Some(key_file) => Some(KeySource::KeyFile(key_file)),
+                None if cli.encrypt_session => {
+                    Some(KeySource::passphrase("Enter session passphrase: ")?)
+                }
+                None => None,
+            };
+
+            run(store.select(cli.profile.as_deref())?, session_key).await
+        }
+    }
+}
+
+/// Returns `path` if given, else the credentials file of the selected profile, else the default credentials file
+fn creds_path(
+    store: &ProfileStore,
+    profile: Option<&str>,
+    path: Option<path::PathBuf>,
+) -> Result<path::PathBuf, Box<dyn Error>> {
+    if let Some(path) = path {
+        return Ok(path);
+    }
+
+    match store.select(profile)? {
+        Some(profile) => Ok(profile.creds_path()),
+        None => Ok(paths::creds_file()?),
+    }
+}
+
+async fn enroll(
+    path: path::PathBuf,
+    verify: bool,
+    key_source: Option<KeySource>,
+) -> Result<(), Box<dyn Error>> {
+    let mut credentials = PromptCredentials.credentials().await?;
+    let roll_number = credentials.roll_number.clone().unwrap_or_default();
+    let endpoints = Endpoints::default();
+
+    println!("Discovering security questions.");
+    let questions = setup::discover_questions(
+        &roll_number,
+        &endpoints,
+        setup::SECURITY_QUESTION_COUNT,
+        setup::DISCOVERY_TRIES,
+    )
+    .await?;
+    if questions.len() < setup::SECURITY_QUESTION_COUNT {
         println!(
-            "Found session file {}. Checking session.",
-            session_file_path.display()
+            "Only found {} of {} security questions, run setup again to find the rest.",
+            questions.len(),
+            setup::SECURITY_QUESTION_COUNT
         );
+    }
 
-        let mut session = Session::default();
-        session.read_session(&session_file_path).await?;
+    let mut answer_map = HashMap::new();
+    for question in questions {
+        let answer = PromptAnswer.answer(&question, &credentials).await?;
+        answer_map.insert(question, answer);
+    }
+    credentials.answer_map = Some(answer_map);
 
-        let is_alive = session.is_alive().await?;
-        println!("Session alive: {}", session.is_alive().await?);
+    if verify {
+        let question = setup::verify_with_otp(&credentials, &endpoints).await?;
+        println!("Verified the password and the answer to {question:?}.");
+    }
 
-        if is_alive {
-            open::that(session.get_login_url(None)?)?;
-            return Ok(());
-        }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
     }
+    match key_source {
+        Some(key_source) => credentials.save_encrypted(&path, &key_source)?,
+        None => credentials.save_to_file(&path)?,
+    }
+    println!("Saved credentials file {}.", path.display());
 
-    let (creds, creds_loaded) = if creds_file_path.exists() {
-        println!("Reading credentials file {}.", creds_file_path.display());
+    Ok(())
+}
 
-        (ErpCreds::from_file(creds_file_path)?, true)
-    } else {
-        let mut rollno = String::new();
+fn manage_profiles(
+    store: &mut ProfileStore,
+    command: ProfileCommand,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        ProfileCommand::List => {
+            let default = store
+                .default_profile()
+                .map(|profile| profile.name().to_owned());
 
-        let stdin = io::stdin();
-        let mut stdout = io::stdout();
+            for profile in store.list() {
+                let marker = if Some(profile.name()) == default.as_deref() {
+                    "*"
+                } else {
+                    " "
+                };
+                println!(
+                    "{marker} {} {}",
+                    profile.name(),
+                    profile.roll_number().unwrap_or("")
+                );
+            }
+        }
+        ProfileCommand::Add {
+            name,
+            roll_number,
+            creds,
+        } => {
+            let profile = store.add(&name, roll_number)?;
+            if let Some(creds) = creds {
+                fs::copy(creds, profile.creds_path())?;
+            }
+            println!("Added profile {name} in {}.", profile.dir().display());
+        }
+        ProfileCommand::Remove { name } => {
+            store.remove(&name)?;
+            println!("Removed profile {name}.");
+        }
+        ProfileCommand::Default { name } => {
+            store.set_default(&name)?;
+            println!("Default profile is now {name}.");
+        }
+    }
 
-        print!("Enter roll number: ");
-        stdout.flush()?;
-        stdin.read_line(&mut rollno)?;
-        let rollno = rollno.trim().to_string();
+    Ok(())
+}
 
-        let password = rpassword::prompt_password("Enter password: ")?;
+async fn run(
+    profile: Option<Profile>,
+    session_key: Option<KeySource>,
+) -> Result<(), Box<dyn Error>> {
+    let (session_file_path, creds_file_path, hub) = match &profile {
+        Some(profile) => {
+            println!("Using profile {}.", profile.name());
 
-        (
-            ErpCreds {
-                roll_number: rollno.into(),
-                password: password.into(),
-                answer_map: None,
-            },
-            false,
-        )
+            (
+                profile.session_path(),
+                profile.creds_path(),
+                GmailAPIObserver::with_paths(
+                    paths::gmail_client_secret()?,
+                    profile.gmail_token_cache_path(),
+                )
+                .await?,
+            )
+        }
+        None => (
+            paths::session_file()?,
+            paths::creds_file()?,
+            GmailAPIObserver::new().await?,
+        ),
     };
 
-    let mut session = Session::new(creds, None);
-    dbg!(session.get_session_token().await?);
+    let credentials = LayeredCredentials::new().file(&creds_file_path);
+    let otp_retriever =
+        hub.or(PromptRetriever::tty().prompt("Email OTP could not be retrieved. Enter manually: "));
 
-    let secret_ques = session.get_secret_question(None).await?;
-    let secret_ans = if !creds_loaded {
-        Some(rpassword::prompt_password(format!("{secret_ques}: "))?)
+    // Security question answers are only stored in the environment or the credentials file
+    if creds_file_path.exists() || !answers_from_env()?.is_empty() {
+        if creds_file_path.exists() {
+            println!("Reading credentials file {}.", creds_file_path.display());
+        }
+
+        login(
+            Login::new(credentials, StoredAnswers, otp_retriever),
+            session_file_path,
+            session_key,
+        )
+        .await
     } else {
-        None
-    };
+        login(
+            Login::new(credentials, PromptAnswer, otp_retriever),
+            session_file_path,
+            session_key,
+        )
+        .await
+    }
+}
 
-    let after_timestamp = session.request_otp(None, secret_ans).await?;
+async fn login<C: CredentialSource, A: AnswerProvider, O: OTPRetriever>(
+    login: Login<C, A, O>,
+    session_file_path: path::PathBuf,
+    session_key: Option<KeySource>,
+) -> Result<(), Box<dyn Error>> {
+    let mut login = login.session_path(&session_file_path);
+    if let Some(session_key) = session_key {
+        login = login.session_key(session_key);
+    }
 
-    let otp = hub.wait_for_otp(after_timestamp, 5).await?;
-    let otp = if let Some(otp) = otp {
-        println!("Obtained OTP from the email.");
-        otp
-    } else {
-        rpassword::prompt_password("Email OTP could not be retrieved. Enter manually: ")?
-    };
+    let session = match login.saved_session().await? {
+        Some((session, status)) if status.is_alive() => {
+            println!("Reusing session from {}.", session_file_path.display());
+            session
+        }
+        saved_session => {
+            if let Some((_, status)) = saved_session {
+                println!("Session {}: {status}.", session_file_path.display());
+            }
 
-    dbg!(session.signin(otp).await?);
+            login.signin().await?
+        }
+    };
 
-    session.save_session(session_file_path).await?;
-    open::that(session.get_login_url(None)?)?;
+    open::that(session.get_login_url(None))?;
 
     Ok(())
 }
@@ -0,0 +1,950 @@
+//! An in-process HTTP server mimicking the ERP SSO flow, for testing without the live ERP.
+//!
+//! Start a [`MockErp`] and point a [`Session`](crate::Session) at it with
+//! [`Session::with_endpoints`](crate::Session::with_endpoints) and [`MockErp::endpoints`].
+//! Use [`MockErp::otp_retriever`] in place of a mailbox to receive the OTP.
+//!
+//! [`MockImap`] is a minimal IMAP server holding ERP OTP emails, to test [`ImapObserver`](crate::imap::ImapObserver).
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use axum::{
+    Form, Json, Router,
+    extract::State,
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{
+    erp::{Endpoints, email, endpoints::paths, responses},
+    otp::OTPRetriever,
+    utils::Res,
+};
+
+/// The account the mock ERP accepts
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// Roll number
+    pub roll_number: String,
+    /// ERP password
+    pub password: String,
+    /// Security questions and their answers
+    pub answers: Vec<(String, String)>,
+    /// Email OTP accepted when signing in
+    pub otp: String,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            roll_number: "21CS10001".into(),
+            password: "password".into(),
+            answers: vec![
+                ("What is your pet's name?".into(), "tommy".into()),
+                ("In which city were you born?".into(), "kharagpur".into()),
+                ("Which is your favourite book?".into(), "gitanjali".into()),
+            ],
+            otp: "123456".into(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    config: MockConfig,
+    /// Number of sessions/tokens handed out, used to generate unique tokens
+    counter: usize,
+    /// Session token issued to each `JSESSIONID`
+    session_tokens: HashMap<String, String>,
+    /// Security question asked to each `JSESSIONID`
+    questions: HashMap<String, String>,
+    /// SSO tokens that are currently logged in
+    sso_tokens: Vec<String>,
+    /// Timestamp of the last OTP sent
+    otp_sent_at: Option<i64>,
+    /// Overrides the `msg` returned when requesting an OTP
+    otp_message: Option<String>,
+    /// Makes the welcome page respond as if ERP were down
+    unavailable: bool,
+}
+
+/// A running mock ERP server. The server shuts down when this is dropped.
+pub struct MockErp {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockErp {
+    /// Starts a mock ERP server on a random local port
+    pub async fn start(config: MockConfig) -> Res<Self> {
+        let state = Arc::new(Mutex::new(MockState {
+            config,
+            ..Default::default()
+        }));
+
+        let app = Router::new()
+            .route(paths::HOMEPAGE, get(homepage))
+            .route(paths::WELCOMEPAGE, get(welcome_page))
+            .route(paths::SECRET_QUESTION, post(security_question))
+            .route(paths::OTP, post(request_otp))
+            .route(paths::LOGIN, post(auth))
+            .fallback(protected_page)
+            .with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// Returns the base URL of the server, e.g. `http://127.0.0.1:8080`
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Returns the endpoints to build a [`Session`](crate::Session) with
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints::from_base_url(&self.base_url())
+    }
+
+    /// Returns the account the server accepts
+    pub fn config(&self) -> MockConfig {
+        lock(&self.state).config.clone()
+    }
+
+    /// Logs out every session, as if they had expired on ERP
+    pub fn expire_sessions(&self) {
+        lock(&self.state).sso_tokens.clear();
+    }
+
+    /// Makes the welcome page respond with `503 Service Unavailable`, as if ERP were down
+    pub fn set_unavailable(&self, unavailable: bool) {
+        lock(&self.state).unavailable = unavailable;
+    }
+
+    /// Makes OTP requests respond with `msg` (e.g. an unknown message) instead of checking the credentials.
+    /// Pass `None` to restore the default behaviour.
+    pub fn set_otp_message(&self, msg: Option<String>) {
+        lock(&self.state).otp_message = msg;
+    }
+
+    /// Returns an [`OTPRetriever`] that receives the OTPs "sent" by this server
+    pub fn otp_retriever(&self) -> MockOTPRetriever {
+        MockOTPRetriever {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Drop for MockErp {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Receives the OTPs sent by a [`MockErp`], in place of a mailbox
+pub struct MockOTPRetriever {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl OTPRetriever for MockOTPRetriever {
+    async fn get_otp(&self, after_timestamp: i64) -> Res<Option<String>> {
+        let state = lock(&self.state);
+
+        Ok(state
+            .otp_sent_at
+            .filter(|sent_at| *sent_at >= after_timestamp)
+            .map(|_| state.config.otp.clone()))
+    }
+}
+
+fn lock<T>(state: &Mutex<T>) -> MutexGuard<'_, T> {
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns the value of a cookie sent with the request
+fn get_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Checks if the request carries the SSO token of a logged in session
+fn is_logged_in(state: &MockState, headers: &HeaderMap) -> bool {
+    get_cookie(headers, "ssoToken")
+        .is_some_and(|sso_token| state.sso_tokens.iter().any(|token| token == sso_token))
+}
+
+fn login_page(session_token: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><title>ERP, IIT Kharagpur</title></head>
+<body>
+<form id="loginFrm" method="post" action="{}">
+<input type="text" id="user_id" name="user_id">
+<input type="password" id="password" name="password">
+<input type="hidden" id="sessionToken" name="sessionToken" value="{session_token}">
+</form>
+</body>
+</html>"#,
+        paths::LOGIN
+    )
+}
+
+async fn homepage(State(state): State<Arc<Mutex<MockState>>>, headers: HeaderMap) -> Response {
+    let mut state = lock(&state);
+    state.counter += 1;
+
+    let session_token = format!("mock-session-token-{}", state.counter);
+    let (jsessionid, new_cookie) = match get_cookie(&headers, "JSESSIONID") {
+        Some(jsessionid) => (jsessionid.to_owned(), false),
+        None => (format!("mock-jsessionid-{}", state.counter), true),
+    };
+    state
+        .session_tokens
+        .insert(jsessionid.clone(), session_token.clone());
+
+    let mut resp = Html(login_page(&session_token)).into_response();
+    if new_cookie {
+        resp.headers_mut().insert(
+            header::SET_COOKIE,
+            HeaderValue::from_str(&format!("JSESSIONID={jsessionid}; Path=/"))
+                .expect("Error setting JSESSIONID cookie."),
+        );
+    }
+
+    resp
+}
+
+async fn welcome_page(State(state): State<Arc<Mutex<MockState>>>, headers: HeaderMap) -> Response {
+    let state = lock(&state);
+
+    let logged_in = is_logged_in(&state, &headers);
+
+    if state.unavailable {
+        (StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable").into_response()
+    } else if logged_in {
+        Html("<html><body>Welcome to ERP, IIT Kharagpur</body></html>").into_response()
+    } else {
+        Html(login_page("")).into_response()
+    }
+}
+
+/// Serves any other page to logged in sessions, and redirects other sessions to the welcome page.
+/// The SSO token is accepted from the cookie or the URL.
+async fn protected_page(
+    State(state): State<Arc<Mutex<MockState>>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let state = lock(&state);
+
+    // ERP also accepts the SSO token in the URL, as in the login URL
+    let sso_token_in_url = uri
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter_map(|pair| pair.split_once('='))
+        .any(|(key, value)| {
+            key == "ssoToken" && state.sso_tokens.iter().any(|token| token == value)
+        });
+
+    if is_logged_in(&state, &headers) || sso_token_in_url {
+        Html(format!("<html><body>{method} {}</body></html>", uri.path())).into_response()
+    } else {
+        Redirect::to(paths::WELCOMEPAGE).into_response()
+    }
+}
+
+async fn security_question(
+    State(state): State<Arc<Mutex<MockState>>>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> String {
+    let mut state = lock(&state);
+
+    if form.get("user_id") != Some(&state.config.roll_number) || state.config.answers.is_empty() {
+        return responses::SECRET_QUES_ROLLNO_INVALID.into();
+    }
+
+    // ERP asks one of the security questions at random; cycle through them instead
+    state.counter += 1;
+    let (question, _) = state.config.answers[state.counter % state.config.answers.len()].clone();
+    let jsessionid = get_cookie(&headers, "JSESSIONID").unwrap_or_default();
+    state
+        .questions
+        .insert(jsessionid.to_owned(), question.clone());
+
+    question
+}
+
+/// Checks the login form against the account, returning the error message if it doesn't match
+fn check_login_form(
+    state: &MockState,
+    headers: &HeaderMap,
+    form: &HashMap<String, String>,
+) -> Option<&'static str> {
+    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or_default();
+    let jsessionid = get_cookie(headers, "JSESSIONID").unwrap_or_default();
+
+    if field("user_id") != state.config.roll_number || field("password") != state.config.password {
+        return Some(responses::PASSWORD_MISMATCH_ERROR);
+    }
+
+    let answer_matches = state.questions.get(jsessionid).is_some_and(|question| {
+        state
+            .config
+            .answers
+            .iter()
+            .any(|(q, a)| q == question && a == field("answer"))
+    });
+    if !answer_matches {
+        return Some(responses::ANSWER_MISMATCH_ERROR);
+    }
+
+    None
+}
+
+async fn request_otp(
+    State(state): State<Arc<Mutex<MockState>>>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Json<HashMap<&'static str, String>> {
+    let mut state = lock(&state);
+
+    let msg = if let Some(msg) = &state.otp_message {
+        msg.clone()
+    } else if let Some(msg) = check_login_form(&state, &headers, &form) {
+        msg.into()
+    } else {
+        state.otp_sent_at = Some(chrono::Local::now().timestamp());
+        responses::OTP_SENT_MESSAGE.into()
+    };
+
+    Json(HashMap::from([("msg", msg)]))
+}
+
+async fn auth(
+    State(state): State<Arc<Mutex<MockState>>>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let mut state = lock(&state);
+
+    let jsessionid = get_cookie(&headers, "JSESSIONID").unwrap_or_default();
+    let session_token_matches = state
+        .session_tokens
+        .get(jsessionid)
+        .is_some_and(|token| Some(token) == form.get("sessionToken"));
+
+    if !session_token_matches || check_login_form(&state, &headers, &form).is_some() {
+        return (StatusCode::UNAUTHORIZED, Html(login_page(""))).into_response();
+    }
+
+    if state.otp_sent_at.is_none() || form.get("email_otp") != Some(&state.config.otp) {
+        return responses::OTP_MISMATCH_ERROR.into_response();
+    }
+
+    state.counter += 1;
+    let sso_token = format!("mock-sso-token-{}", state.counter);
+    state.sso_tokens.push(sso_token.clone());
+
+    let requested_url = form
+        .get("requestedUrl")
+        .map(String::as_str)
+        .unwrap_or(paths::HOMEPAGE);
+    let mut resp = Redirect::to(&format!("{requested_url}?ssoToken={sso_token}")).into_response();
+    resp.headers_mut().insert(
+        header::SET_COOKIE,
+        HeaderValue::from_str(&format!("ssoToken={sso_token}; Path=/"))
+            .expect("Error setting ssoToken cookie."),
+    );
+
+    resp
+}
+
+/// An email held by a [`MockImap`]
+#[derive(Debug, Clone)]
+struct MockMail {
+    from: String,
+    subject: String,
+    date: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl MockMail {
+    /// The header block, as sent for `BODY[HEADER]`
+    fn header(&self) -> String {
+        format!(
+            "From: {}\r\nTo: student@example.com\r\nSubject: {}\r\nDate: {}\r\n\r\n",
+            self.from,
+            self.subject,
+            self.date.to_rfc2822()
+        )
+    }
+
+    /// Checks a `UID SEARCH` key and its argument. `SINCE` compares dates in the email's time zone.
+    fn matches(&self, key: &str, value: &str) -> bool {
+        let contains = |field: &str| field.to_lowercase().contains(&value.to_lowercase());
+
+        match key.to_ascii_uppercase().as_str() {
+            "FROM" => contains(&self.from),
+            "SUBJECT" => contains(&self.subject),
+            "SINCE" => chrono::NaiveDate::parse_from_str(value, "%d-%b-%Y")
+                .is_ok_and(|since| self.date.date_naive() >= since),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MockImapState {
+    username: String,
+    password: String,
+    /// Emails in the mailbox, the UID of each is its index plus one
+    mails: Vec<MockMail>,
+}
+
+/// A running mock IMAP server with one mailbox, speaking just enough unencrypted IMAP for
+/// [`ImapObserver`](crate::imap::ImapObserver) with [`ImapSecurity::Plain`](crate::imap::ImapSecurity::Plain).
+/// The server shuts down when this is dropped.
+pub struct MockImap {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockImapState>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockImap {
+    /// Starts a mock IMAP server on a random local port, accepting the given login
+    pub async fn start(username: &str, password: &str) -> Res<Self> {
+        let state = Arc::new(Mutex::new(MockImapState {
+            username: username.into(),
+            password: password.into(),
+            mails: Vec::new(),
+        }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve_imap(stream, state.clone()));
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// Returns the host of the server
+    pub fn host(&self) -> String {
+        self.addr.ip().to_string()
+    }
+
+    /// Returns the port of the server
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Adds an email to the mailbox, received at `timestamp`
+    pub fn deliver(&self, from: &str, subject: &str, timestamp: i64) {
+        let date = chrono::DateTime::from_timestamp(timestamp, 0)
+            .expect("Invalid timestamp.")
+            .fixed_offset();
+
+        lock(&self.state).mails.push(MockMail {
+            from: from.into(),
+            subject: subject.into(),
+            date,
+        });
+    }
+
+    /// Adds an ERP OTP email carrying `otp` to the mailbox, received at `timestamp`
+    pub fn deliver_otp(&self, otp: &str, timestamp: i64) {
+        self.deliver(
+            &format!("ERP, IIT Kharagpur <{}>", email::ERP_EMAIL),
+            &format!("{} {otp}", email::ERP_OTP_SUBJECT_PREFIX),
+            timestamp,
+        );
+    }
+}
+
+impl Drop for MockImap {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Splits IMAP command arguments, keeping quoted strings together and unescaping them
+fn imap_args(args: &str) -> Vec<String> {
+    let mut parsed = Vec::new();
+    let mut chars = args.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c == ' ' {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut arg = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => arg.extend(chars.next()),
+                    c => arg.push(c),
+                }
+            }
+            parsed.push(arg);
+        } else {
+            let mut arg = String::new();
+            while let Some(c) = chars.next_if(|c| *c != ' ') {
+                arg.push(c);
+            }
+            parsed.push(arg);
+        }
+    }
+
+    parsed
+}
+
+/// Responds to a tagged IMAP command, returning `None` to close the connection
+fn imap_response(
+    state: &Mutex<MockImapState>,
+    tag: &str,
+    command: &str,
+    args: &str,
+) -> Option<String> {
+    let state = lock(state);
+    let args = imap_args(args);
+
+    let response = match command.to_ascii_uppercase().as_str() {
+        "CAPABILITY" => format!("* CAPABILITY IMAP4rev1\r\n{tag} OK CAPABILITY completed\r\n"),
+        "LOGIN" => {
+            if args.len() == 2 && args[0] == state.username && args[1] == state.password {
+                format!("{tag} OK LOGIN completed\r\n")
+            } else {
+                format!("{tag} NO [AUTHENTICATIONFAILED] Invalid credentials\r\n")
+            }
+        }
+        "SELECT" | "EXAMINE" => format!(
+            "* {} EXISTS\r\n* 0 RECENT\r\n* FLAGS (\\Seen)\r\n* OK [UIDVALIDITY 1] UIDs valid\r\n* OK [UIDNEXT {}] Predicted next UID\r\n{tag} OK [READ-WRITE] SELECT completed\r\n",
+            state.mails.len(),
+            state.mails.len() + 1
+        ),
+        "UID"
+            if args
+                .first()
+                .is_some_and(|arg| arg.eq_ignore_ascii_case("SEARCH")) =>
+        {
+            let uids: Vec<String> = (1..=state.mails.len())
+                .filter(|uid| {
+                    args[1..].chunks(2).all(|key| {
+                        state.mails[uid - 1].matches(&key[0], key.get(1).map_or("", String::as_str))
+                    })
+                })
+                .map(|uid| uid.to_string())
+                .collect();
+
+            format!(
+                "* SEARCH {}\r\n{tag} OK SEARCH completed\r\n",
+                uids.join(" ")
+            )
+        }
+        "UID"
+            if args
+                .first()
+                .is_some_and(|arg| arg.eq_ignore_ascii_case("FETCH")) =>
+        {
+            let mut response = String::new();
+            for uid in args.get(1).into_iter().flat_map(|set| set.split(',')) {
+                let Some((uid, mail)) = uid
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|uid| Some((uid, state.mails.get(uid.checked_sub(1)?)?)))
+                else {
+                    continue;
+                };
+
+                let header = mail.header();
+                response.push_str(&format!(
+                    "* {uid} FETCH (UID {uid} BODY[HEADER] {{{}}}\r\n{header})\r\n",
+                    header.len()
+                ));
+            }
+
+            format!("{response}{tag} OK FETCH completed\r\n")
+        }
+        "NOOP" => format!("{tag} OK NOOP completed\r\n"),
+        "LOGOUT" => return None,
+        _ => format!("{tag} BAD Unknown command\r\n"),
+    };
+
+    Some(response)
+}
+
+/// Serves one IMAP connection
+async fn serve_imap(stream: TcpStream, state: Arc<Mutex<MockImapState>>) -> Res<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    writer
+        .write_all(b"* OK [CAPABILITY IMAP4rev1] Mock IMAP ready\r\n")
+        .await?;
+    while let Some(line) = lines.next_line().await? {
+        let mut parts = line.trim_end().splitn(3, ' ');
+        let (tag, command, args) = (
+            parts.next().unwrap_or_default(),
+            parts.next().unwrap_or_default(),
+            parts.next().unwrap_or_default(),
+        );
+
+        match imap_response(&state, tag, command, args) {
+            Some(response) => writer.write_all(response.as_bytes()).await?,
+            None => {
+                let response = format!("* BYE Logging out\r\n{tag} OK LOGOUT completed\r\n");
+                writer.write_all(response.as_bytes()).await?;
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        ErpError, Login, ReloginEvent, ReloginSession, Session, SessionStatus,
+        answer::StoredAnswers, crypto::KeySource, session::SignedIn, utils::ErpCreds,
+    };
+
+    fn credentials(config: &MockConfig) -> ErpCreds {
+        ErpCreds {
+            roll_number: Some(config.roll_number.clone()),
+            password: Some(config.password.as_str().into()),
+            answer_map: Some(
+                config
+                    .answers
+                    .iter()
+                    .map(|(question, answer)| (question.clone(), answer.as_str().into()))
+                    .collect(),
+            ),
+        }
+    }
+
+    async fn signin(erp: &MockErp, credentials: ErpCreds) -> Res<Session<SignedIn>> {
+        let session = Session::with_endpoints(credentials, None, erp.endpoints())
+            .get_secret_question(None)
+            .await?
+            .request_otp(None, &StoredAnswers)
+            .await?;
+
+        session.signin(erp.config().otp.into()).await
+    }
+
+    #[tokio::test]
+    async fn signs_in() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let session = Session::with_endpoints(credentials(&erp.config()), None, erp.endpoints())
+            .get_secret_question(None)
+            .await
+            .unwrap();
+        assert!(
+            erp.config()
+                .answers
+                .iter()
+                .any(|(q, _)| q == session.question())
+        );
+
+        let session = session.request_otp(None, &StoredAnswers).await.unwrap();
+        let otp = erp
+            .otp_retriever()
+            .get_otp(session.otp_requested_at())
+            .await
+            .unwrap()
+            .unwrap();
+        let session = session.signin(otp.into()).await.unwrap();
+
+        assert!(session.sso_token().expose().starts_with("mock-sso-token-"));
+        assert!(session.is_alive().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_roll_number() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let credentials = ErpCreds {
+            roll_number: Some("21CS10002".into()),
+            ..credentials(&erp.config())
+        };
+
+        let result = Session::with_endpoints(credentials, None, erp.endpoints())
+            .get_secret_question(None)
+            .await;
+        assert!(matches!(result, Err(ErpError::InvalidRollNumber { .. })));
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_password() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let credentials = ErpCreds {
+            password: Some("wrong".into()),
+            ..credentials(&erp.config())
+        };
+
+        let result = signin(&erp, credentials).await;
+        assert!(matches!(result, Err(ErpError::PasswordMismatch { .. })));
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_answer() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let mut credentials = credentials(&erp.config());
+        for answer in credentials.answer_map.as_mut().unwrap().values_mut() {
+            *answer = "wrong".into();
+        }
+
+        let result = signin(&erp, credentials).await;
+        assert!(matches!(result, Err(ErpError::AnswerMismatch { .. })));
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_otp() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let session = Session::with_endpoints(credentials(&erp.config()), None, erp.endpoints())
+            .get_secret_question(None)
+            .await
+            .unwrap()
+            .request_otp(None, &StoredAnswers)
+            .await
+            .unwrap();
+
+        let result = session.signin("000000".into()).await;
+        assert!(matches!(result, Err(ErpError::OtpMismatch { .. })));
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_otp_response() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        erp.set_otp_message(Some("ERP is under maintenance".into()));
+
+        let result = signin(&erp, credentials(&erp.config())).await;
+        assert!(
+            matches!(result, Err(ErpError::OtpRequestFailed { response }) if response == "ERP is under maintenance")
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_session_status() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let mut session = signin(&erp, credentials(&erp.config())).await.unwrap();
+        assert!(matches!(session.verify().await, SessionStatus::Alive(_)));
+
+        erp.set_unavailable(true);
+        assert!(matches!(
+            session.verify().await,
+            SessionStatus::ErpUnavailable(_)
+        ));
+
+        erp.set_unavailable(false);
+        erp.expire_sessions();
+        assert!(matches!(session.verify().await, SessionStatus::Expired(_)));
+    }
+
+    #[tokio::test]
+    async fn gets_pages_on_live_session() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let session = signin(&erp, credentials(&erp.config())).await.unwrap();
+
+        let page = session.get("/IIT_ERP3/menu.htm").await.unwrap();
+        assert!(
+            page.text()
+                .await
+                .unwrap()
+                .contains("GET /IIT_ERP3/menu.htm")
+        );
+
+        // A logged in session is served the welcome page without the login form
+        let welcome_page = session.get(&erp.endpoints().welcome_page).await.unwrap();
+        assert!(welcome_page.url().query().is_none());
+        assert!(welcome_page.text().await.unwrap().contains("Welcome"));
+    }
+
+    #[tokio::test]
+    async fn reports_logged_out_without_sso_token() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let session = signin(&erp, credentials(&erp.config())).await.unwrap();
+        erp.expire_sessions();
+
+        for path in ["/IIT_ERP3/menu.htm", &erp.endpoints().welcome_page] {
+            let err = session.get(path).await.unwrap_err();
+            assert!(
+                matches!(&err, ErpError::LoggedOut { url } if url == &erp.endpoints().welcome_page)
+            );
+            assert!(!err.to_string().contains(session.sso_token().expose()));
+        }
+    }
+
+    #[tokio::test]
+    async fn saves_and_reads_session() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let session = signin(&erp, credentials(&erp.config())).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("erp-mock-session-{}", std::process::id()));
+        session.save_session(&path).await.unwrap();
+        let read = Session::with_endpoints(ErpCreds::default(), None, erp.endpoints())
+            .read_session(&path)
+            .await;
+        let _ = std::fs::remove_file(&path);
+
+        let read = read.unwrap();
+        assert_eq!(read.sso_token(), session.sso_token());
+        assert!(read.is_alive().await.unwrap());
+    }
+
+    fn login(erp: &MockErp) -> Login<ErpCreds, StoredAnswers, MockOTPRetriever> {
+        Login::new(
+            credentials(&erp.config()),
+            StoredAnswers,
+            erp.otp_retriever(),
+        )
+        .endpoints(erp.endpoints())
+        .otp_tries(1)
+    }
+
+    #[tokio::test]
+    async fn login_replaces_corrupt_session_file() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let path =
+            std::env::temp_dir().join(format!("erp-mock-corrupt-session-{}", std::process::id()));
+        std::fs::write(&path, r#"{"name": "not a session"}"#).unwrap();
+
+        let login = login(&erp).session_path(&path);
+        let session = login.run().await;
+        let saved = login.saved_session().await;
+        let _ = std::fs::remove_file(&path);
+
+        assert!(session.unwrap().is_alive().await.unwrap());
+        assert!(matches!(saved, Ok(Some((_, status))) if status.is_alive()));
+    }
+
+    #[tokio::test]
+    async fn login_reports_encrypted_session_file() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let session = signin(&erp, credentials(&erp.config())).await.unwrap();
+        let path =
+            std::env::temp_dir().join(format!("erp-mock-locked-session-{}", std::process::id()));
+        session
+            .save_encrypted_session(
+                &path,
+                &KeySource::Passphrase("correct horse".to_owned().into()),
+            )
+            .await
+            .unwrap();
+
+        let result = login(&erp).session_path(&path).run().await;
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(result, Err(ErpError::SessionEncrypted)));
+    }
+
+    #[tokio::test]
+    async fn relogs_in_when_logged_out() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut session = ReloginSession::new(login(&erp)).await.unwrap().on_relogin({
+            let events = events.clone();
+            move |event: &ReloginEvent| events.borrow_mut().push(event.clone())
+        });
+        let sso_token = session.session().sso_token().expose().clone();
+
+        erp.expire_sessions();
+        let page = session.get("/IIT_ERP3/menu.htm").await.unwrap();
+        assert!(
+            page.text()
+                .await
+                .unwrap()
+                .contains("GET /IIT_ERP3/menu.htm")
+        );
+        assert_ne!(session.session().sso_token().expose(), &sso_token);
+        {
+            let events = events.borrow();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].request_url, "/IIT_ERP3/menu.htm");
+            assert_eq!(events[0].landed_url, erp.endpoints().welcome_page);
+            assert_eq!(events[0].error, None);
+        }
+
+        erp.expire_sessions();
+        erp.set_otp_message(Some("ERP is under maintenance".into()));
+        let result = session.get("/IIT_ERP3/menu.htm").await;
+        assert!(matches!(result, Err(ErpError::OtpRequestFailed { .. })));
+        let events = events.borrow();
+        assert_eq!(events.len(), 2);
+        assert!(
+            events[1]
+                .error
+                .as_ref()
+                .is_some_and(|error| error.contains("ERP is under maintenance"))
+        );
+    }
+
+    #[tokio::test]
+    async fn saves_and_reads_encrypted_session() {
+        let erp = MockErp::start(MockConfig::default()).await.unwrap();
+        let session = signin(&erp, credentials(&erp.config())).await.unwrap();
+        let key_source = KeySource::Passphrase("correct horse".to_owned().into());
+        let read_session = || Session::with_endpoints(ErpCreds::default(), None, erp.endpoints());
+
+        let path =
+            std::env::temp_dir().join(format!("erp-mock-encrypted-session-{}", std::process::id()));
+        session
+            .save_encrypted_session(&path, &key_source)
+            .await
+            .unwrap();
+        let without_key = read_session().read_session(&path).await;
+        let wrong_key = read_session()
+            .read_encrypted_session(&path, &KeySource::Passphrase("wrong".to_owned().into()))
+            .await;
+        let read = read_session()
+            .read_encrypted_session(&path, &key_source)
+            .await;
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(without_key, Err(ErpError::SessionEncrypted)));
+        assert!(matches!(wrong_key, Err(ErpError::DecryptionFailed)));
+        let read = read.unwrap();
+        assert_eq!(read.sso_token(), session.sso_token());
+        assert!(read.is_alive().await.unwrap());
+    }
+}
@@ -1,8 +1,18 @@
-use std::time::Duration;
+use std::{
+    io::{ErrorKind, Write},
+    pin::Pin,
+    thread,
+    time::Duration,
+};
 
-use tokio::time::sleep;
+use futures_util::{StreamExt, stream::FuturesUnordered};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader, Empty},
+    sync::{Mutex, oneshot},
+    time::sleep,
+};
 
-use crate::utils::Res;
+use crate::{error::ErpError, utils::Res};
 
 pub trait OTPRetriever {
     fn get_otp(&self, after_timestamp: i64) -> impl Future<Output = Res<Option<String>>>;
@@ -25,6 +35,270 @@ pub trait OTPRetriever {
             Ok(None)
         }
     }
+
+    /// Falls back to `fallback` if this retriever does not find the OTP (see [`Fallback`])
+    fn or<O: OTPRetriever>(self, fallback: O) -> Fallback<Self, O>
+    where
+        Self: Sized,
+    {
+        Fallback {
+            primary: self,
+            fallback,
+        }
+    }
+}
+
+/// Retrieves the OTP with `primary`, and with `fallback` if `primary` does not find it or fails.
+/// Errors from `primary` are printed before trying `fallback`. Built with [`OTPRetriever::or`].
+pub struct Fallback<P, F> {
+    primary: P,
+    fallback: F,
+}
+
+impl<P: OTPRetriever, F: OTPRetriever> OTPRetriever for Fallback<P, F> {
+    async fn get_otp(&self, after_timestamp: i64) -> Res<Option<String>> {
+        match self.primary.get_otp(after_timestamp).await {
+            Ok(Some(otp)) => Ok(Some(otp)),
+            Ok(None) => self.fallback.get_otp(after_timestamp).await,
+            Err(err) => {
+                println!("Retrieving the OTP failed: {err}");
+                self.fallback.get_otp(after_timestamp).await
+            }
+        }
+    }
+
+    /// Waits for the OTP with `primary`, then with `fallback`, each checking up to `tries` times
+    async fn wait_for_otp(&self, after_timestamp: i64, tries: usize) -> Res<Option<String>> {
+        match self.primary.wait_for_otp(after_timestamp, tries).await {
+            Ok(Some(otp)) => Ok(Some(otp)),
+            Ok(None) => self.fallback.wait_for_otp(after_timestamp, tries).await,
+            Err(err) => {
+                println!("Retrieving the OTP failed: {err}");
+                self.fallback.wait_for_otp(after_timestamp, tries).await
+            }
+        }
+    }
+}
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Object-safe form of [`OTPRetriever`], so that retrievers of different types can be raced
+trait DynOTPRetriever {
+    fn get_otp_boxed(&self, after_timestamp: i64) -> BoxFuture<'_, Res<Option<String>>>;
+    fn wait_for_otp_boxed(
+        &self,
+        after_timestamp: i64,
+        tries: usize,
+    ) -> BoxFuture<'_, Res<Option<String>>>;
+}
+
+impl<O: OTPRetriever> DynOTPRetriever for O {
+    fn get_otp_boxed(&self, after_timestamp: i64) -> BoxFuture<'_, Res<Option<String>>> {
+        Box::pin(self.get_otp(after_timestamp))
+    }
+
+    fn wait_for_otp_boxed(
+        &self,
+        after_timestamp: i64,
+        tries: usize,
+    ) -> BoxFuture<'_, Res<Option<String>>> {
+        Box::pin(self.wait_for_otp(after_timestamp, tries))
+    }
+}
+
+/// Retrieves the OTP from several named sources at once, e.g. Gmail, IMAP, a webhook and the keyboard,
+/// taking the first valid OTP and cancelling the other sources.
+/// The winning source is printed and returned by [`Race::winner`].
+///
+/// A source that fails or finds no OTP drops out of the race. If none finds it, the first error is returned.
+#[derive(Default)]
+pub struct Race {
+    sources: Vec<(String, Box<dyn DynOTPRetriever>)>,
+    winner: std::sync::Mutex<Option<String>>,
+}
+
+impl Race {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a source, named in messages and by [`Race::winner`]
+    pub fn source<O: OTPRetriever + 'static>(
+        mut self,
+        name: impl Into<String>,
+        retriever: O,
+    ) -> Self {
+        self.sources.push((name.into(), Box::new(retriever)));
+        self
+    }
+
+    /// Returns the name of the source that found the last OTP
+    pub fn winner(&self) -> Option<String> {
+        self.winner
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// Runs `retrieve` on every source concurrently, returning the first valid OTP
+    async fn race<'a>(
+        &'a self,
+        retrieve: impl Fn(&'a dyn DynOTPRetriever) -> BoxFuture<'a, Res<Option<String>>>,
+    ) -> Res<Option<String>> {
+        let mut running: FuturesUnordered<_> = self
+            .sources
+            .iter()
+            .map(|(name, retriever)| {
+                let retrieval = retrieve(retriever.as_ref());
+                async move { (name, retrieval.await) }
+            })
+            .collect();
+
+        let mut first_err = None;
+        while let Some((name, result)) = running.next().await {
+            match result {
+                Ok(Some(otp)) if is_otp(&otp) => {
+                    println!("Got the OTP from {name}.");
+                    *self.winner.lock().unwrap_or_else(|err| err.into_inner()) = Some(name.clone());
+
+                    // Dropping the other retrievals cancels them
+                    return Ok(Some(otp));
+                }
+                Ok(Some(_)) => println!("{name} found an invalid OTP."),
+                Ok(None) => {}
+                Err(err) => {
+                    println!("{name} failed: {err}");
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+}
+
+impl OTPRetriever for Race {
+    async fn get_otp(&self, after_timestamp: i64) -> Res<Option<String>> {
+        self.race(|retriever| retriever.get_otp_boxed(after_timestamp))
+            .await
+    }
+
+    /// Waits for the OTP from every source at once, each checking up to `tries` times
+    async fn wait_for_otp(&self, after_timestamp: i64, tries: usize) -> Res<Option<String>> {
+        self.race(|retriever| retriever.wait_for_otp_boxed(after_timestamp, tries))
+            .await
+    }
+}
+
+/// Default prompt of a [`PromptRetriever`]
+pub const DEFAULT_OTP_PROMPT: &str = "Enter email OTP: ";
+/// Default number of times a [`PromptRetriever`] asks again for an invalid OTP
+pub const DEFAULT_PROMPT_ATTEMPTS: usize = 3;
+
+enum PromptInput<R> {
+    /// The terminal, read without echoing
+    Tty,
+    Reader(Mutex<BufReader<R>>),
+}
+
+/// Asks the user for the OTP, on the terminal or from any reader such as a pipe.
+/// Each line read is checked with [`is_otp`]; an invalid OTP is asked for again, up to a number of attempts.
+/// Finds no OTP if the reader ends first.
+pub struct PromptRetriever<R = Empty> {
+    input: PromptInput<R>,
+    prompt: String,
+    attempts: usize,
+}
+
+impl PromptRetriever {
+    /// Asks on the terminal, without echoing the OTP as it is typed.
+    /// If the prompt is cancelled, the line being read is still consumed by it.
+    pub fn tty() -> Self {
+        Self::with_input(PromptInput::Tty)
+    }
+}
+
+impl<R: AsyncRead + Unpin> PromptRetriever<R> {
+    /// Reads the OTP from `reader`, one line per attempt
+    pub fn from_reader(reader: R) -> Self {
+        Self::with_input(PromptInput::Reader(Mutex::new(BufReader::new(reader))))
+    }
+
+    fn with_input(input: PromptInput<R>) -> Self {
+        Self {
+            input,
+            prompt: DEFAULT_OTP_PROMPT.into(),
+            attempts: DEFAULT_PROMPT_ATTEMPTS,
+        }
+    }
+
+    /// Prompt shown before reading the OTP. Defaults to [`DEFAULT_OTP_PROMPT`].
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// Number of times to ask for the OTP if it is invalid. Defaults to [`DEFAULT_PROMPT_ATTEMPTS`].
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    /// Shows the prompt and reads a line, returning `None` at the end of the input
+    async fn read_line(&self) -> Res<Option<String>> {
+        print!("{}", self.prompt);
+        std::io::stdout().flush()?;
+
+        match &self.input {
+            PromptInput::Tty => {
+                // Reads on a detached thread, which neither blocks other retrievers running alongside (see [`Race`])
+                // nor keeps the runtime from shutting down if the prompt is cancelled
+                let (tx, rx) = oneshot::channel();
+                thread::spawn(move || {
+                    let read = match rpassword::read_password() {
+                        Ok(line) => Ok(Some(line)),
+                        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+                        Err(err) => Err(err),
+                    };
+                    let _ = tx.send(read);
+                });
+
+                Ok(rx.await.map_err(std::io::Error::other)??)
+            }
+            PromptInput::Reader(reader) => {
+                let mut line = String::new();
+                let read = reader.lock().await.read_line(&mut line).await?;
+
+                Ok((read > 0).then_some(line))
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> OTPRetriever for PromptRetriever<R> {
+    async fn get_otp(&self, _after_timestamp: i64) -> Res<Option<String>> {
+        for _ in 0..self.attempts {
+            let Some(line) = self.read_line().await? else {
+                return Ok(None);
+            };
+
+            let otp = line.trim();
+            if is_otp(otp) {
+                return Ok(Some(otp.to_owned()));
+            }
+            println!("An OTP is 6 digits.");
+        }
+
+        Err(ErpError::Parse("no valid OTP entered".into()))
+    }
+
+    /// Asks once, without waiting: the user enters the OTP when it arrives
+    async fn wait_for_otp(&self, after_timestamp: i64, _tries: usize) -> Res<Option<String>> {
+        self.get_otp(after_timestamp).await
+    }
 }
 
 pub fn is_otp(str: &str) -> bool {
@@ -37,3 +311,122 @@ pub fn get_otp_from_sub(subject: &str) -> Option<String> {
         .find(|str| is_otp(str))
         .map(|str| str.to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A retriever that always fails
+    struct Failing;
+
+    impl OTPRetriever for Failing {
+        async fn get_otp(&self, _after_timestamp: i64) -> Res<Option<String>> {
+            Err(ErpError::Parse("mailbox unreachable".into()))
+        }
+
+        async fn wait_for_otp(&self, after_timestamp: i64, _tries: usize) -> Res<Option<String>> {
+            self.get_otp(after_timestamp).await
+        }
+    }
+
+    /// A retriever that finds `result` after `delay`, or fails with its message
+    struct Stub {
+        delay: Duration,
+        result: Result<Option<&'static str>, &'static str>,
+    }
+
+    impl Stub {
+        fn new(delay_ms: u64, result: Result<Option<&'static str>, &'static str>) -> Self {
+            Self {
+                delay: Duration::from_millis(delay_ms),
+                result,
+            }
+        }
+    }
+
+    impl OTPRetriever for Stub {
+        async fn get_otp(&self, _after_timestamp: i64) -> Res<Option<String>> {
+            sleep(self.delay).await;
+
+            self.result
+                .map(|otp| otp.map(str::to_owned))
+                .map_err(|err| ErpError::Parse(err.into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn race_takes_fastest_otp() {
+        let race = Race::new()
+            .source("slow", Stub::new(200, Ok(Some("111111"))))
+            .source("fast", Stub::new(10, Ok(Some("222222"))))
+            .source("failing", Failing)
+            .source("empty", Stub::new(0, Ok(None)));
+
+        assert_eq!(race.get_otp(0).await.unwrap().as_deref(), Some("222222"));
+        assert_eq!(race.winner().as_deref(), Some("fast"));
+    }
+
+    #[tokio::test]
+    async fn race_drops_invalid_otp() {
+        let race = Race::new()
+            .source("invalid", Stub::new(0, Ok(Some("12ab56"))))
+            .source("valid", Stub::new(20, Ok(Some("123456"))));
+
+        assert_eq!(race.get_otp(0).await.unwrap().as_deref(), Some("123456"));
+        assert_eq!(race.winner().as_deref(), Some("valid"));
+    }
+
+    #[tokio::test]
+    async fn race_returns_first_error() {
+        let race = Race::new()
+            .source("second", Stub::new(50, Err("second")))
+            .source("first", Stub::new(10, Err("first")))
+            .source("empty", Stub::new(0, Ok(None)));
+
+        assert!(matches!(race.get_otp(0).await, Err(ErpError::Parse(err)) if err == "first"));
+        assert_eq!(race.winner(), None);
+    }
+
+    #[tokio::test]
+    async fn race_finds_no_otp() {
+        let race = Race::new()
+            .source("empty", Stub::new(0, Ok(None)))
+            .source("invalid", Stub::new(10, Ok(Some("1234"))));
+
+        assert_eq!(race.get_otp(0).await.unwrap(), None);
+        assert_eq!(race.winner(), None);
+    }
+
+    #[tokio::test]
+    async fn prompt_asks_again_for_invalid_otp() {
+        let prompt = PromptRetriever::from_reader(&b"12345\nabcdef\n 123456 \n"[..]);
+        assert_eq!(prompt.get_otp(0).await.unwrap().as_deref(), Some("123456"));
+    }
+
+    #[tokio::test]
+    async fn prompt_finds_no_otp_at_end_of_input() {
+        let prompt = PromptRetriever::from_reader(&b"12345\n"[..]);
+        assert_eq!(prompt.get_otp(0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn prompt_fails_after_attempts() {
+        let prompt = PromptRetriever::from_reader(&b"1\n2\n123456\n"[..]).attempts(2);
+        assert!(matches!(prompt.get_otp(0).await, Err(ErpError::Parse(_))));
+    }
+
+    #[tokio::test]
+    async fn fallback_follows_failed_primary() {
+        let retriever = Failing.or(PromptRetriever::from_reader(&b"123456\n"[..]));
+        assert_eq!(
+            retriever.get_otp(0).await.unwrap().as_deref(),
+            Some("123456")
+        );
+
+        let retriever = Failing.or(PromptRetriever::from_reader(&b"654321\n"[..]));
+        assert_eq!(
+            retriever.wait_for_otp(0, 0).await.unwrap().as_deref(),
+            Some("654321")
+        );
+    }
+}
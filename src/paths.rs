@@ -0,0 +1,138 @@
+//! Default locations of the files used by the login flow, under the XDG base directories.
+//!
+//! The Gmail client secret is configuration, credentials, the Gmail token cache and profiles are data, and the
+//! session file is state. Each directory and file can be overridden with an environment variable.
+//! A file in the working directory from before these defaults is moved to the default location the first time it
+//! is resolved, if the default file does not exist yet, so every working directory uses the same files afterwards.
+
+use std::{env, fs, path::PathBuf};
+
+use crate::{error::ErpError, gmail, profile, utils::Res};
+
+/// Name of the directory created in each XDG base directory
+pub const APP_DIR: &str = "iitkgp-erp-login";
+
+/// Overrides the configuration directory
+pub const CONFIG_DIR_ENV_VAR: &str = "ERP_CONFIG_DIR";
+/// Overrides the data directory
+pub const DATA_DIR_ENV_VAR: &str = "ERP_DATA_DIR";
+/// Overrides the state directory
+pub const STATE_DIR_ENV_VAR: &str = "ERP_STATE_DIR";
+/// Overrides the credentials file
+pub const CREDS_FILE_ENV_VAR: &str = "ERP_CREDS_FILE";
+/// Overrides the session file
+pub const SESSION_FILE_ENV_VAR: &str = "ERP_SESSION_FILE";
+/// Overrides the Gmail API client secret
+pub const GMAIL_CLIENT_SECRET_ENV_VAR: &str = "ERP_GMAIL_CLIENT_SECRET";
+/// Overrides the Gmail API token cache
+pub const GMAIL_TOKEN_CACHE_ENV_VAR: &str = "ERP_GMAIL_TOKEN_CACHE";
+/// Overrides the profile store directory
+pub const PROFILES_DIR_ENV_VAR: &str = "ERP_PROFILES_DIR";
+
+/// Reads a non-empty path from an environment variable
+fn env_path(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Resolves an XDG base directory (from `xdg_var`, or `fallback` under the home directory) joined with [`APP_DIR`]
+fn base_dir(override_var: &str, xdg_var: &str, fallback: &str) -> Res<PathBuf> {
+    if let Some(dir) = env_path(override_var) {
+        return Ok(dir);
+    }
+
+    let base = env_path(xdg_var)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env_path("HOME").map(|home| home.join(fallback)))
+        .ok_or(ErpError::Missing("home directory"))?;
+
+    Ok(base.join(APP_DIR))
+}
+
+/// Resolves a file: the override from `override_var`, else the file in `dir`.
+/// The legacy file in the working directory is moved there if only that one exists.
+fn file(override_var: &str, dir: Res<PathBuf>, name: &str, legacy_name: &str) -> Res<PathBuf> {
+    if let Some(path) = env_path(override_var) {
+        return Ok(path);
+    }
+
+    let path = dir?.join(name);
+    let legacy_path = PathBuf::from(legacy_name);
+    if !path.exists() && legacy_path.is_file() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Renaming fails across file systems, so the file is copied then
+        if fs::rename(&legacy_path, &path).is_err() {
+            fs::copy(&legacy_path, &path)?;
+            fs::remove_file(&legacy_path)?;
+        }
+        println!("Moved {} to {}.", legacy_path.display(), path.display());
+    }
+
+    Ok(path)
+}
+
+/// Configuration directory: `$ERP_CONFIG_DIR`, or `$XDG_CONFIG_HOME/iitkgp-erp-login` (`~/.config/iitkgp-erp-login`)
+pub fn config_dir() -> Res<PathBuf> {
+    base_dir(CONFIG_DIR_ENV_VAR, "XDG_CONFIG_HOME", ".config")
+}
+
+/// Data directory: `$ERP_DATA_DIR`, or `$XDG_DATA_HOME/iitkgp-erp-login` (`~/.local/share/iitkgp-erp-login`)
+pub fn data_dir() -> Res<PathBuf> {
+    base_dir(DATA_DIR_ENV_VAR, "XDG_DATA_HOME", ".local/share")
+}
+
+/// State directory: `$ERP_STATE_DIR`, or `$XDG_STATE_HOME/iitkgp-erp-login` (`~/.local/state/iitkgp-erp-login`)
+pub fn state_dir() -> Res<PathBuf> {
+    base_dir(STATE_DIR_ENV_VAR, "XDG_STATE_HOME", ".local/state")
+}
+
+/// Gmail API client secret: `$ERP_GMAIL_CLIENT_SECRET`, or `gmail_client_secret.json` in the configuration directory
+pub fn gmail_client_secret() -> Res<PathBuf> {
+    file(
+        GMAIL_CLIENT_SECRET_ENV_VAR,
+        config_dir(),
+        gmail::CLIENT_SECRET_FILE,
+        gmail::CLIENT_SECRET_FILE,
+    )
+}
+
+/// Gmail API token cache: `$ERP_GMAIL_TOKEN_CACHE`, or `gmail_token_cache.json` in the data directory
+pub fn gmail_token_cache() -> Res<PathBuf> {
+    file(
+        GMAIL_TOKEN_CACHE_ENV_VAR,
+        data_dir(),
+        gmail::TOKEN_CACHE_FILE,
+        gmail::TOKEN_CACHE_FILE,
+    )
+}
+
+/// Credentials file: `$ERP_CREDS_FILE`, or `erpcreds.json` in the data directory
+pub fn creds_file() -> Res<PathBuf> {
+    file(
+        CREDS_FILE_ENV_VAR,
+        data_dir(),
+        profile::CREDS_FILE,
+        "erpcreds.json",
+    )
+}
+
+/// Session file: `$ERP_SESSION_FILE`, or `session` in the state directory
+pub fn session_file() -> Res<PathBuf> {
+    file(
+        SESSION_FILE_ENV_VAR,
+        state_dir(),
+        profile::SESSION_FILE,
+        ".session",
+    )
+}
+
+/// Profile store directory: `$ERP_PROFILES_DIR`, or `profiles` in the data directory
+pub fn profiles_dir() -> Res<PathBuf> {
+    match env_path(PROFILES_DIR_ENV_VAR) {
+        Some(dir) => Ok(dir),
+        None => Ok(data_dir()?.join("profiles")),
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ErpError, utils::Res};
+
+/// Index of the profiles in a profile store
+pub const PROFILES_FILE: &str = "profiles.json";
+/// Credentials file of a profile
+pub const CREDS_FILE: &str = "erpcreds.json";
+/// Session file of a profile
+pub const SESSION_FILE: &str = "session";
+/// Gmail API token cache of a profile
+pub const GMAIL_TOKEN_CACHE_FILE: &str = "gmail_token_cache.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProfileIndex {
+    default: Option<String>,
+    profiles: BTreeMap<String, ProfileEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProfileEntry {
+    roll_number: Option<String>,
+}
+
+/// An ERP identity in a [`ProfileStore`], with its own credentials, session file and Gmail token cache
+#[derive(Debug, Clone)]
+pub struct Profile {
+    name: String,
+    roll_number: Option<String>,
+    dir: PathBuf,
+}
+
+impl Profile {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn roll_number(&self) -> Option<&str> {
+        self.roll_number.as_deref()
+    }
+
+    /// Directory holding the profile's files
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Credentials file of the profile (plaintext or encrypted)
+    pub fn creds_path(&self) -> PathBuf {
+        self.dir.join(CREDS_FILE)
+    }
+
+    /// Session file of the profile
+    pub fn session_path(&self) -> PathBuf {
+        self.dir.join(SESSION_FILE)
+    }
+
+    /// Gmail API token cache of the profile
+    pub fn gmail_token_cache_path(&self) -> PathBuf {
+        self.dir.join(GMAIL_TOKEN_CACHE_FILE)
+    }
+}
+
+/// Profiles of several ERP identities, stored as one directory per profile and an index (`profiles.json`)
+pub struct ProfileStore {
+    root: PathBuf,
+    index: ProfileIndex,
+}
+
+impl ProfileStore {
+    /// Opens the profile store in `root`, which is created when a profile is added
+    pub fn open<P: Into<PathBuf>>(root: P) -> Res<Self> {
+        let root = root.into();
+        let index_path = root.join(PROFILES_FILE);
+
+        let index = if index_path.exists() {
+            serde_json::from_slice(&fs::read(&index_path)?)
+                .map_err(|err| ErpError::Profile(format!("corrupt {PROFILES_FILE}: {err}")))?
+        } else {
+            ProfileIndex::default()
+        };
+
+        Ok(Self { root, index })
+    }
+
+    /// Directory of the profile store
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Lists the profiles, sorted by name
+    pub fn list(&self) -> Vec<Profile> {
+        self.index
+            .profiles
+            .iter()
+            .map(|(name, entry)| self.profile(name, entry))
+            .collect()
+    }
+
+    /// Finds a profile by name, or by roll number if no profile has that name
+    pub fn get(&self, name_or_roll_number: &str) -> Res<Profile> {
+        if let Some(entry) = self.index.profiles.get(name_or_roll_number) {
+            return Ok(self.profile(name_or_roll_number, entry));
+        }
+
+        self.index
+            .profiles
+            .iter()
+            .find(|(_, entry)| entry.roll_number.as_deref() == Some(name_or_roll_number))
+            .map(|(name, entry)| self.profile(name, entry))
+            .ok_or_else(|| ErpError::Profile(format!("no profile {name_or_roll_number}")))
+    }
+
+    /// Returns the default profile, if one is set
+    pub fn default_profile(&self) -> Option<Profile> {
+        let name = self.index.default.as_ref()?;
+
+        self.index
+            .profiles
+            .get(name)
+            .map(|entry| self.profile(name, entry))
+    }
+
+    /// Returns the profile named `name_or_roll_number` if given (see [`ProfileStore::get`]), and the default profile otherwise
+    pub fn select(&self, name_or_roll_number: Option<&str>) -> Res<Option<Profile>> {
+        match name_or_roll_number {
+            Some(name_or_roll_number) => self.get(name_or_roll_number).map(Some),
+            None => Ok(self.default_profile()),
+        }
+    }
+
+    /// Adds a profile and creates its directory. The first profile added becomes the default.
+    pub fn add(&mut self, name: &str, roll_number: Option<String>) -> Res<Profile> {
+        if name.is_empty()
+            || name.starts_with('.')
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(ErpError::Profile(format!(
+                "invalid profile name {name:?}: use letters, digits, '-', '_' and '.'"
+            )));
+        }
+        if self.index.profiles.contains_key(name) {
+            return Err(ErpError::Profile(format!("profile {name} already exists")));
+        }
+
+        let entry = ProfileEntry { roll_number };
+        let profile = self.profile(name, &entry);
+        fs::create_dir_all(profile.dir())?;
+
+        self.index.profiles.insert(name.to_owned(), entry);
+        if self.index.default.is_none() {
+            self.index.default = Some(name.to_owned());
+        }
+        self.save()?;
+
+        Ok(profile)
+    }
+
+    /// Removes a profile and deletes its files. Removing the default profile leaves no default.
+    pub fn remove(&mut self, name: &str) -> Res<()> {
+        let entry = self
+            .index
+            .profiles
+            .remove(name)
+            .ok_or_else(|| ErpError::Profile(format!("no profile {name}")))?;
+        if self.index.default.as_deref() == Some(name) {
+            self.index.default = None;
+        }
+        self.save()?;
+
+        let dir = self.profile(name, &entry).dir;
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes a profile the default
+    pub fn set_default(&mut self, name: &str) -> Res<()> {
+        if !self.index.profiles.contains_key(name) {
+            return Err(ErpError::Profile(format!("no profile {name}")));
+        }
+
+        self.index.default = Some(name.to_owned());
+        self.save()
+    }
+
+    fn profile(&self, name: &str, entry: &ProfileEntry) -> Profile {
+        Profile {
+            name: name.to_owned(),
+            roll_number: entry.roll_number.clone(),
+            dir: self.root.join(name),
+        }
+    }
+
+    /// Saves the index, replacing the old one only once it is written
+    fn save(&self) -> Res<()> {
+        fs::create_dir_all(&self.root)?;
+
+        let index_path = self.root.join(PROFILES_FILE);
+        let tmp_path = self.root.join(format!("{PROFILES_FILE}.tmp"));
+        fs::write(&tmp_path, serde_json::to_string_pretty(&self.index)?)?;
+
+        Ok(fs::rename(tmp_path, index_path)?)
+    }
+}
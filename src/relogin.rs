@@ -0,0 +1,132 @@
+use reqwest::{RequestBuilder, Response};
+use serde::Serialize;
+
+use crate::{
+    answer::AnswerProvider,
+    creds::CredentialSource,
+    error::ErpError,
+    login::Login,
+    otp::OTPRetriever,
+    session::{Session, SignedIn},
+    status,
+    utils::Res,
+};
+
+/// Details of a re-authentication, passed to the hooks of a [`ReloginSession`]
+#[derive(Debug, Clone)]
+pub struct ReloginEvent {
+    /// URL or ERP path of the request that found the session logged out
+    pub request_url: String,
+    /// URL the response ended at, without its query: the login page or the welcome page
+    pub landed_url: String,
+    /// Error message if the login flow failed
+    pub error: Option<String>,
+}
+
+/// A hook observing re-authentications
+type ReloginHook = Box<dyn Fn(&ReloginEvent)>;
+
+/// A signed in session that logs in again when ERP logs it out.
+///
+/// When a request comes back on the login page or the login form ([`ErpError::LoggedOut`]), the login flow is run once
+/// (see [`Login::signin`]) and the request is retried with the new session.
+pub struct ReloginSession<C, A, O> {
+    login: Login<C, A, O>,
+    session: Session<SignedIn>,
+    hooks: Vec<ReloginHook>,
+}
+
+impl<C: CredentialSource, A: AnswerProvider, O: OTPRetriever> ReloginSession<C, A, O> {
+    /// Logs in with `login` (reusing its saved session if alive)
+    pub async fn new(login: Login<C, A, O>) -> Res<Self> {
+        let session = login.run().await?;
+
+        Ok(Self::from_session(login, session))
+    }
+
+    /// Wraps an already signed in session
+    pub fn from_session(login: Login<C, A, O>, session: Session<SignedIn>) -> Self {
+        Self {
+            login,
+            session,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Adds a hook called after each re-authentication
+    pub fn on_relogin<F: Fn(&ReloginEvent) + 'static>(mut self, hook: F) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Returns the current session
+    pub fn session(&self) -> &Session<SignedIn> {
+        &self.session
+    }
+
+    /// Fetches an ERP page with [`Session::get`], logging in again and retrying once if the session was logged out
+    pub async fn get(&mut self, path: &str) -> Res<Response> {
+        self.with_relogin(path.into(), async |session| session.get(path).await)
+            .await
+    }
+
+    /// Posts a form to an ERP page with [`Session::post`], logging in again and retrying once if the session was logged out
+    pub async fn post<T: Serialize + ?Sized>(&mut self, path: &str, form: &T) -> Res<Response> {
+        self.with_relogin(path.into(), async |session| session.post(path, form).await)
+            .await
+    }
+
+    /// Sends the request built by `request`, logging in again and retrying once if the session was logged out.
+    /// `request` is called again with the new session for the retry.
+    pub async fn send<F>(&mut self, request: F) -> Res<Response>
+    where
+        F: Fn(&Session<SignedIn>) -> RequestBuilder,
+    {
+        let request_url = request(&self.session).build()?.url().to_string();
+
+        self.with_relogin(request_url, async |session| {
+            let resp = request(session).send().await?;
+
+            status::ensure_logged_in(resp, session.endpoints()).await
+        })
+        .await
+    }
+
+    /// Runs `request`, and if it fails with [`ErpError::LoggedOut`], runs the login flow and `request` again
+    async fn with_relogin<F>(&mut self, request_url: String, request: F) -> Res<Response>
+    where
+        F: AsyncFn(&Session<SignedIn>) -> Res<Response>,
+    {
+        let landed_url = match request(&self.session).await {
+            Err(ErpError::LoggedOut { url }) => url,
+            result => return result,
+        };
+
+        let mut event = ReloginEvent {
+            request_url,
+            landed_url,
+            error: None,
+        };
+
+        match self.login.signin().await {
+            Ok(session) => {
+                self.session = session;
+                self.notify(&event);
+            }
+            Err(err) => {
+                event.error = Some(err.to_string());
+                self.notify(&event);
+
+                return Err(err);
+            }
+        }
+
+        request(&self.session).await
+    }
+
+    fn notify(&self, event: &ReloginEvent) {
+        for hook in &self.hooks {
+            hook(event);
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use chrono::{DateTime, Utc};
 use reqwest::{
-    Client, Url,
+    Client, RequestBuilder, Response, Url,
     header::{HeaderMap, USER_AGENT},
 };
 use reqwest_cookie_store::{CookieStore, CookieStoreMutex, RawCookie};
 use scraper::{Html, Selector};
+use serde::Serialize;
 use std::{
     collections::HashMap,
     path::{self, Path},
@@ -11,25 +13,57 @@ use std::{
     sync::Arc,
 };
 
-use crate::erp::{endpoints, responses};
-use crate::utils::{ErpCreds, Res, read_session_file, save_session_file};
+use crate::answer::AnswerProvider;
+use crate::crypto::KeySource;
+use crate::erp::{Endpoints, responses};
+use crate::error::ErpError;
+use crate::paths;
+use crate::secret::Secret;
+use crate::session_file::SessionFile;
+use crate::status::{self, SessionStatus};
+use crate::utils::{ErpCreds, Res};
+
+/// Login stage of a new session. The next step is [`Session::get_secret_question`].
+pub struct Fresh;
+
+/// Login stage after the security question is fetched. The next step is [`Session::request_otp`].
+pub struct QuestionFetched {
+    /// The security question for this session
+    question: String,
+}
+
+/// Login stage after ERP sends the email OTP. The next step is [`Session::signin`].
+pub struct OtpRequested {
+    /// Secret/security question's answer for this session
+    answer: Secret<String>,
+    /// Timestamp just before the OTP was requested
+    after_timestamp: i64,
+}
 
-pub struct Session {
+/// Login stage of a signed in session
+pub struct SignedIn {
+    /// SSO token
+    sso_token: Secret<String>,
+    /// When the session was signed in
+    created_at: DateTime<Utc>,
+    /// When the session was last found to be alive
+    last_verified_at: Option<DateTime<Utc>>,
+}
+
+/// An ERP session. The type parameter is the stage of the login flow, which decides the methods available:
+/// [`Fresh`] → [`QuestionFetched`] → [`OtpRequested`] → [`SignedIn`].
+pub struct Session<S = Fresh> {
     cookie_store: Arc<CookieStoreMutex>,
     client: Client,
     credentials: ErpCreds,
-    /// The security question for this session
-    question: Option<String>,
-    /// Secret/security question's answer for this session
-    answer: Option<String>,
-    /// OTP for this session
-    email_otp: Option<String>,
     /// Session token
-    session_token: Option<String>,
-    /// SSO token
-    sso_token: Option<String>,
+    session_token: Option<Secret<String>>,
     /// Headers for the post requests
     headers: HeaderMap,
+    /// ERP URLs this session talks to
+    endpoints: Endpoints,
+    /// Data for the current login stage
+    stage: S,
 }
 
 fn get_default_headers() -> HeaderMap {
@@ -48,8 +82,95 @@ fn get_default_headers() -> HeaderMap {
     headers
 }
 
-impl Session {
+impl<S> Session<S> {
+    /// Returns the ERP URLs this session talks to
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.endpoints
+    }
+
+    /// Returns the credentials of this session
+    pub fn credentials(&self) -> &ErpCreds {
+        &self.credentials
+    }
+
+    /// Checks whether the session is logged in on ERP, from the page ERP serves for the welcome page
+    pub async fn status(&self) -> SessionStatus {
+        let has_sso_token = Url::from_str(&self.endpoints.base_url).is_ok_and(|base_url| {
+            self.cookie_store.lock().is_ok_and(|store| {
+                store
+                    .get_request_values(&base_url)
+                    .any(|(name, _)| name == "ssoToken")
+            })
+        });
+
+        status::check(&self.client, &self.endpoints, has_sso_token).await
+    }
+
+    /// Discards the login progress and cookies, returning a fresh session with the same credentials
+    pub fn reset(self) -> Session<Fresh> {
+        Session::with_endpoints(self.credentials, Some(self.headers), self.endpoints)
+    }
+
+    /// Moves the session to the next login stage
+    fn advance<T>(self, stage: T) -> Session<T> {
+        Session {
+            cookie_store: self.cookie_store,
+            client: self.client,
+            credentials: self.credentials,
+            session_token: self.session_token,
+            headers: self.headers,
+            endpoints: self.endpoints,
+            stage,
+        }
+    }
+
+    /// Returns the form data for login requests, borrowing the secrets instead of copying them
+    fn get_login_details<'a>(
+        &'a self,
+        answer: &'a Secret<String>,
+        email_otp: &'a str,
+    ) -> Res<Vec<(&'static str, &'a str)>> {
+        let user_id = self
+            .credentials
+            .roll_number
+            .as_ref()
+            .ok_or(ErpError::Missing("roll number"))?;
+
+        let password = self
+            .credentials
+            .password
+            .as_ref()
+            .ok_or(ErpError::Missing("password"))?;
+
+        let session_token = self
+            .session_token
+            .as_ref()
+            .ok_or(ErpError::MissingSessionToken)?;
+
+        Ok(vec![
+            ("user_id", user_id),
+            ("password", password.expose()),
+            ("answer", answer.expose()),
+            // No idea what this is
+            ("typeee", "SI"),
+            ("email_otp", email_otp),
+            ("sessionToken", session_token.expose()),
+            ("requestedUrl", &self.endpoints.homepage),
+        ])
+    }
+}
+
+impl Session<Fresh> {
     pub fn new(credentials: ErpCreds, headers: Option<HeaderMap>) -> Session {
+        Self::with_endpoints(credentials, headers, Endpoints::default())
+    }
+
+    /// Creates a session that talks to the given endpoints instead of the live ERP
+    pub fn with_endpoints(
+        credentials: ErpCreds,
+        headers: Option<HeaderMap>,
+        endpoints: Endpoints,
+    ) -> Session {
         let cookie_store = CookieStoreMutex::new(CookieStore::new());
         let cookie_store = Arc::new(cookie_store);
 
@@ -61,34 +182,21 @@ impl Session {
             cookie_store,
             headers: headers.unwrap_or(get_default_headers()),
             credentials,
-            question: None,
-            answer: None,
             session_token: None,
-            sso_token: None,
-            email_otp: None,
-        }
-    }
-
-    /// Checks if the session is alive
-    pub async fn is_alive(&self) -> Res<bool> {
-        let resp = self.client.get(endpoints::WELCOMEPAGE_URL).send().await?;
-
-        if let Some(len) = resp.content_length() {
-            Ok(len == 1034)
-        } else {
-            Ok(false)
+            endpoints,
+            stage: Fresh,
         }
     }
 
     /// Fetches the session token
-    pub async fn get_session_token(&mut self) -> Res<String> {
+    pub async fn get_session_token(&mut self) -> Res<Secret<String>> {
         if let Some(session_token) = &self.session_token {
-            return Ok(session_token.to_owned());
+            return Ok(session_token.clone());
         }
 
         let homepage = self
             .client
-            .get(endpoints::HOMEPAGE_URL)
+            .get(&self.endpoints.homepage)
             .send()
             .await?
             .text()
@@ -96,39 +204,46 @@ impl Session {
 
         let document = Html::parse_document(&homepage);
 
-        let session_token_selector = Selector::parse("#sessionToken")?;
+        let session_token_selector =
+            Selector::parse("#sessionToken").map_err(|err| ErpError::Parse(err.to_string()))?;
         let mut elements = document.select(&session_token_selector);
 
         if let Some(elem) = elements.next() {
-            let session_token: String = elem
+            let session_token: Secret<String> = elem
                 .attr("value")
                 .map(|val| val.into())
-                .ok_or(String::from("Error: session token not found."))?;
-            self.session_token = session_token.clone().into();
+                .ok_or(ErpError::MissingSessionToken)?;
+            self.session_token = Some(session_token.clone());
 
             Ok(session_token)
         } else {
-            Err(String::from("Error: Session token selector element not found.").into())
+            Err(ErpError::MissingSessionToken)
         }
     }
 
     /// Fetches the secret question given the rollnumber. If the rollnumber is set in the session struct, it is used instead.
-    pub async fn get_secret_question(&mut self, roll_number: Option<String>) -> Res<String> {
+    /// Also fetches the session token if it has not been fetched yet.
+    pub async fn get_secret_question(
+        mut self,
+        roll_number: Option<String>,
+    ) -> Res<Session<QuestionFetched>> {
         let roll_number = if let Some(roll_number) = &self.credentials.roll_number {
             roll_number.clone()
         } else {
-            let roll_number = roll_number.ok_or("Error: No roll number found.")?;
+            let roll_number = roll_number.ok_or(ErpError::Missing("roll number"))?;
             self.credentials.roll_number = roll_number.clone().into();
 
             roll_number
         };
 
+        self.get_session_token().await?;
+
         let mut form_data = HashMap::new();
         form_data.insert("user_id", roll_number);
 
         let resp = self
             .client
-            .post(endpoints::SECRET_QUESTION_URL)
+            .post(&self.endpoints.security_question)
             .form(&form_data)
             .headers(self.headers.clone())
             .send()
@@ -137,51 +252,109 @@ impl Session {
             .await?;
 
         if resp == responses::SECRET_QUES_ROLLNO_INVALID {
-            Err("Error: Invalid roll number.".into())
+            Err(ErpError::InvalidRollNumber { response: resp })
         } else {
-            self.question = resp.clone().into();
+            Ok(self.advance(QuestionFetched { question: resp }))
+        }
+    }
+
+    /// Loads a signed in session from a saved session file. This loads the session token, sso token and cookies,
+    /// and the roll number if the session has none, but not the other credentials.
+    /// Legacy session files are migrated; they are rewritten in the current format when the session is saved again.
+    /// Encrypted session files fail with [`ErpError::SessionEncrypted`], see [`Session::read_encrypted_session`].
+    pub async fn read_session<P: AsRef<Path>>(self, file_path: P) -> Res<Session<SignedIn>> {
+        self.read_session_with_key(file_path, None).await
+    }
+
+    /// Loads a signed in session from the default session file (see [`paths::session_file`])
+    pub async fn read_default_session(self) -> Res<Session<SignedIn>> {
+        self.read_session(paths::session_file()?).await
+    }
+
+    /// Loads a signed in session from a session file encrypted with the key from `key_source`.
+    /// Unencrypted session files are read as with [`Session::read_session`].
+    pub async fn read_encrypted_session<P: AsRef<Path>>(
+        self,
+        file_path: P,
+        key_source: &KeySource,
+    ) -> Res<Session<SignedIn>> {
+        self.read_session_with_key(file_path, Some(key_source))
+            .await
+    }
 
-            Ok(resp)
+    pub(crate) async fn read_session_with_key<P: AsRef<Path>>(
+        mut self,
+        file_path: P,
+        key_source: Option<&KeySource>,
+    ) -> Res<Session<SignedIn>> {
+        let file_path = path::absolute(file_path)?;
+
+        let session_file = SessionFile::read(file_path, key_source).await?;
+        self.session_token = session_file.session_token;
+        if self.credentials.roll_number.is_none() {
+            self.credentials.roll_number = session_file.roll_number;
+        }
+
+        {
+            let mut store = self
+                .cookie_store
+                .lock()
+                .map_err(|_| ErpError::CookieStore("cookie store lock poisoned".into()))?;
+
+            if !session_file.cookies.is_empty() {
+                *store = CookieStore::from_cookies(
+                    session_file.cookies.into_iter().map(Ok::<_, ErpError>),
+                    true,
+                )?;
+            } else {
+                // Legacy sessions saved without cookies only have the SSO token
+                store.clear();
+
+                let sso_token_cookie =
+                    RawCookie::new("ssoToken", session_file.sso_token.expose().as_str());
+                let base_url = Url::from_str(&self.endpoints.base_url)
+                    .map_err(|err| ErpError::Parse(err.to_string()))?;
+                store
+                    .insert_raw(&sso_token_cookie, &base_url)
+                    .map_err(|err| ErpError::CookieStore(err.to_string()))?;
+            }
         }
+
+        Ok(self.advance(SignedIn {
+            sso_token: session_file.sso_token,
+            created_at: session_file.created_at,
+            last_verified_at: session_file.last_verified_at,
+        }))
+    }
+}
+
+impl Session<QuestionFetched> {
+    /// Returns the security question for this session
+    pub fn question(&self) -> &str {
+        &self.stage.question
     }
 
     /// Requests ERP to send an OTP.
-    pub async fn request_otp(
-        &mut self,
-        password: Option<String>,
-        answer: Option<String>,
-    ) -> Res<i64> {
+    /// The answer to this session's security question is asked from `answers`.
+    pub async fn request_otp<A: AnswerProvider>(
+        mut self,
+        password: Option<Secret<String>>,
+        answers: &A,
+    ) -> Res<Session<OtpRequested>> {
         if self.credentials.password.is_none() {
-            let password = password.ok_or("Error: Password not found.")?;
-            self.credentials.password = password.clone().into();
+            let password = password.ok_or(ErpError::Missing("password"))?;
+            self.credentials.password = Some(password);
         }
 
-        if let Some(answer) = answer {
-            self.answer = answer.clone().into();
-        } else {
-            let answer_map = self
-                .credentials
-                .answer_map
-                .as_ref()
-                .ok_or("Error: No security question answers found.")?;
-
-            let question = self
-                .question
-                .as_ref()
-                .ok_or("Error: Security question for this session not found.")?;
-
-            self.answer = answer_map
-                .get(question)
-                .ok_or("Error: Answer to the security question not found.")?
-                .to_owned()
-                .into();
-        }
+        let answer = answers
+            .answer(&self.stage.question, &self.credentials)
+            .await?;
 
-        let login_details = self.get_login_details()?;
+        let login_details = self.get_login_details(&answer, "")?;
 
         let resp = self
             .client
-            .post(endpoints::OTP_URL)
+            .post(&self.endpoints.otp)
             .form(&login_details)
             .headers(self.headers.clone())
             .build()?;
@@ -191,27 +364,36 @@ impl Session {
         let resp: HashMap<String, String> = resp.json().await?;
 
         if let Some(msg) = resp.get("msg") {
+            let response = msg.to_owned();
+
             match msg.as_str() {
-                responses::ANSWER_MISMATCH_ERROR => {
-                    Err("Incorrect security question answer.".into())
-                }
-                responses::PASSWORD_MISMATCH_ERROR => Err("Incorrect password.".into()),
-                responses::OTP_SENT_MESSAGE => Ok(after_timestamp),
-                _ => Err(format!("Error requesting OTP: {msg}").into()),
+                responses::ANSWER_MISMATCH_ERROR => Err(ErpError::AnswerMismatch { response }),
+                responses::PASSWORD_MISMATCH_ERROR => Err(ErpError::PasswordMismatch { response }),
+                responses::OTP_SENT_MESSAGE => Ok(self.advance(OtpRequested {
+                    answer,
+                    after_timestamp,
+                })),
+                _ => Err(ErpError::OtpRequestFailed { response }),
             }
         } else {
-            Err("Error: Response has no `msg` field.".into())
+            Err(ErpError::Parse("OTP response has no `msg` field".into()))
         }
     }
+}
+
+impl Session<OtpRequested> {
+    /// Returns the timestamp just before the OTP was requested, to look for OTP emails received after it
+    pub fn otp_requested_at(&self) -> i64 {
+        self.stage.after_timestamp
+    }
 
-    /// Logs into ERP for the current session. Returns the ssoToken
-    pub async fn signin(&mut self, otp: String) -> Res<String> {
-        self.email_otp = Some(otp);
-        let login_details = self.get_login_details()?;
+    /// Logs into ERP for the current session
+    pub async fn signin(self, otp: Secret<String>) -> Res<Session<SignedIn>> {
+        let login_details = self.get_login_details(&self.stage.answer, otp.expose())?;
 
         let resp = self
             .client
-            .post(endpoints::LOGIN_URL)
+            .post(&self.endpoints.auth)
             .form(&login_details)
             .headers(self.headers.clone())
             .send()
@@ -219,121 +401,168 @@ impl Session {
 
         let final_url = resp.url().to_owned();
 
-        if resp.text().await?.as_str() == responses::OTP_MISMATCH_ERROR {
-            return Err("OTP mismatch".into());
+        let text = resp.text().await?;
+        if text == responses::OTP_MISMATCH_ERROR {
+            return Err(ErpError::OtpMismatch { response: text });
         }
 
         if let Some(sso_token_pair) = final_url.query_pairs().find(|pair| pair.0 == "ssoToken") {
-            let sso_token = sso_token_pair.1.to_string();
-            self.sso_token = Some(sso_token.clone());
+            let sso_token = Secret::new(sso_token_pair.1.into_owned());
 
-            Ok(sso_token)
+            Ok(self.advance(SignedIn {
+                sso_token,
+                created_at: Utc::now(),
+                last_verified_at: Some(Utc::now()),
+            }))
         } else {
-            Err("SSO token not found in URL.".into())
+            Err(ErpError::MissingSsoToken {
+                url: final_url.to_string(),
+            })
         }
     }
+}
 
-    /// Returns a link to log into ERP with credentials
-    /// Opens the homepage by default
-    pub fn get_login_url(&self, url: Option<&str>) -> Res<String> {
-        if let Some(sso_token) = &self.sso_token {
-            Ok(format!(
-                "{}?ssoToken={sso_token}",
-                url.unwrap_or(endpoints::HOMEPAGE_URL)
-            ))
-        } else {
-            Err("Error: Session not logged in.".into())
-        }
+impl Session<SignedIn> {
+    /// Returns the SSO token
+    pub fn sso_token(&self) -> &Secret<String> {
+        &self.stage.sso_token
     }
 
-    /// Saves the session on a file
-    pub async fn save_session<P: AsRef<Path>>(&self, file_path: P) -> Res<()> {
-        let file_path = path::absolute(file_path)?;
+    /// Returns the authenticated HTTP client carrying this session's cookies, for requests not covered by
+    /// [`Session::get`] and [`Session::post`]
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
 
-        save_session_file(
-            file_path,
-            self.session_token.as_deref(),
-            self.sso_token.as_deref(),
-        )
-        .await
+    /// Fetches an ERP page. `path` is relative to the base URL, or a full URL.
+    pub async fn get(&self, path: &str) -> Res<Response> {
+        self.send_authenticated(path, |url| self.client.get(url))
+            .await
     }
 
-    /// Loads a session from a saved session file. This only loads the session token and sso token (if they exist), not the credentials.
-    pub async fn read_session<P: AsRef<Path>>(&mut self, file_path: P) -> Res<()> {
-        let file_path = path::absolute(file_path)?;
+    /// Posts a form to an ERP page. `path` is relative to the base URL, or a full URL.
+    pub async fn post<T: Serialize + ?Sized>(&self, path: &str, form: &T) -> Res<Response> {
+        self.send_authenticated(path, |url| self.client.post(url).form(form))
+            .await
+    }
 
-        let (session_token, sso_token) = read_session_file(file_path).await?;
-        self.session_token = session_token;
-        self.sso_token = sso_token;
+    /// Sends the request built by `request` for `path`. If ERP sends it to the login page or serves the login form,
+    /// the request is retried with the SSO token in the URL (as with [`Session::get_login_url`]),
+    /// which makes ERP redirect back to the requested page. The token is kept out of errors.
+    async fn send_authenticated<F>(&self, path: &str, request: F) -> Res<Response>
+    where
+        F: Fn(Url) -> RequestBuilder,
+    {
+        let url = Url::parse(&self.endpoints.base_url)
+            .and_then(|base_url| base_url.join(path))
+            .map_err(|err| ErpError::Parse(err.to_string()))?;
+
+        let resp = request(url.clone())
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        match status::ensure_logged_in(resp, &self.endpoints).await {
+            Err(ErpError::LoggedOut { .. }) => {}
+            result => return result,
+        }
 
-        if let Some(sso_token) = &self.sso_token {
-            let mut store = self
-                .cookie_store
-                .lock()
-                .map_err(|_| "Error getting cookie store.".to_string())?;
+        let mut url = url;
+        url.query_pairs_mut()
+            .append_pair("ssoToken", self.stage.sso_token.expose());
 
-            store.clear();
+        let resp = request(url)
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        status::ensure_logged_in(resp, &self.endpoints).await
+    }
 
-            let sso_token_cookie = RawCookie::new("ssoToken", sso_token);
-            store.insert_raw(&sso_token_cookie, &Url::from_str(endpoints::BASE_URL)?)?;
+    /// Checks if the session is alive. See [`Session::status`] for the details.
+    pub async fn is_alive(&self) -> Res<bool> {
+        Ok(self.status().await.is_alive())
+    }
+
+    /// Checks the status of the session like [`Session::status`], recording the time if it is alive
+    pub async fn verify(&mut self) -> SessionStatus {
+        let status = self.status().await;
+        if status.is_alive() {
+            self.stage.last_verified_at = Some(Utc::now());
         }
 
-        Ok(())
+        status
     }
 
-    /// Returns the form data for login requests
-    fn get_login_details(&self) -> Res<Vec<(&'static str, String)>> {
-        let user_id = self
-            .credentials
-            .roll_number
-            .as_ref()
-            .ok_or("Error: Roll number not found.")?
-            .clone();
+    /// Returns when the session was signed in
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.stage.created_at
+    }
 
-        let password = self
-            .credentials
-            .password
-            .as_ref()
-            .ok_or("Error: Password not found.")?
-            .clone();
+    /// Returns when the session was last found to be alive
+    pub fn last_verified_at(&self) -> Option<DateTime<Utc>> {
+        self.stage.last_verified_at
+    }
 
-        let answer = self
-            .answer
-            .as_ref()
-            .ok_or("Error: Secret question answer not found.")?
-            .clone();
+    /// Returns a link to log into ERP with credentials
+    /// Opens the homepage by default
+    pub fn get_login_url(&self, url: Option<&str>) -> String {
+        format!(
+            "{}?ssoToken={}",
+            url.unwrap_or(&self.endpoints.homepage),
+            self.stage.sso_token.expose()
+        )
+    }
 
-        let session_token = self
-            .session_token
-            .as_ref()
-            .ok_or("Error: Session token not found.")?
-            .clone();
+    /// Saves the session, including all of its cookies, on a file. See [`SessionFile`] for the format.
+    pub async fn save_session<P: AsRef<Path>>(&self, file_path: P) -> Res<()> {
+        self.save_session_with_key(file_path, None).await
+    }
 
-        Ok(vec![
-            ("user_id", user_id),
-            ("password", password),
-            ("answer", answer),
-            // No idea what this is
-            ("typeee", "SI".into()),
-            (
-                "email_otp",
-                self.email_otp.clone().unwrap_or("".into()).clone(),
-            ),
-            ("sessionToken", session_token),
-            ("requestedUrl", endpoints::HOMEPAGE_URL.into()),
-        ])
+    /// Saves the session on the default session file (see [`paths::session_file`])
+    pub async fn save_default_session(&self) -> Res<()> {
+        self.save_session(paths::session_file()?).await
+    }
+
+    /// Saves the session on a file encrypted with the key from `key_source`
+    pub async fn save_encrypted_session<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        key_source: &KeySource,
+    ) -> Res<()> {
+        self.save_session_with_key(file_path, Some(key_source))
+            .await
+    }
+
+    pub(crate) async fn save_session_with_key<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        key_source: Option<&KeySource>,
+    ) -> Res<()> {
+        let file_path = path::absolute(file_path)?;
+
+        let cookies: Vec<_> = self
+            .cookie_store
+            .lock()
+            .map_err(|_| ErpError::CookieStore("cookie store lock poisoned".into()))?
+            .iter_any()
+            .cloned()
+            .collect();
+
+        SessionFile::new(
+            self.credentials.roll_number.clone(),
+            self.session_token.clone(),
+            self.stage.sso_token.clone(),
+            cookies,
+            self.stage.created_at,
+            self.stage.last_verified_at,
+        )
+        .save(file_path, key_source)
+        .await
     }
 }
 
 impl Default for Session {
     fn default() -> Self {
-        Self::new(
-            ErpCreds {
-                roll_number: None,
-                password: None,
-                answer_map: None,
-            },
-            None,
-        )
+        Self::new(ErpCreds::default(), None)
     }
 }
@@ -0,0 +1,386 @@
+use std::{fmt, path::Path};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use cookie_store::{Cookie, CookieExpiration};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::{
+    crypto::{Encrypted, KeySource},
+    error::ErpError,
+    secret::Secret,
+    utils::Res,
+};
+
+/// Identifies a file as a session file written by this crate
+pub const SESSION_FILE_FORMAT: &str = "iitkgp-erp-login/session";
+/// Latest version of the session file format
+pub const SESSION_FILE_VERSION: u32 = 1;
+/// How long a session is estimated to stay alive after it was last verified, when ERP does not set an expiry on the SSO token
+pub const ESTIMATED_SESSION_LIFETIME: TimeDelta = TimeDelta::hours(1);
+
+/// A saved ERP session, stored as versioned JSON with a checksum.
+/// Legacy session files (the session token and SSO token on two lines) are migrated when read.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionFile {
+    format: String,
+    version: u32,
+    /// Roll number the session belongs to
+    pub roll_number: Option<String>,
+    /// Session token
+    pub session_token: Option<Secret<String>>,
+    /// SSO token
+    pub sso_token: Secret<String>,
+    /// All cookies of the session
+    pub cookies: Vec<Cookie<'static>>,
+    /// When the session was signed in
+    pub created_at: DateTime<Utc>,
+    /// When the session was last found to be alive
+    pub last_verified_at: Option<DateTime<Utc>>,
+    /// When the session is estimated to expire
+    pub expires_at: Option<DateTime<Utc>>,
+    /// SHA-256 of the file with this field empty
+    checksum: String,
+}
+
+/// Prints the cookie names only, as their values include the SSO token
+impl fmt::Debug for SessionFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cookies: Vec<String> = self
+            .cookies
+            .iter()
+            .map(|cookie| format!("{}=[REDACTED]", cookie.name()))
+            .collect();
+
+        f.debug_struct("SessionFile")
+            .field("format", &self.format)
+            .field("version", &self.version)
+            .field("roll_number", &self.roll_number)
+            .field("session_token", &self.session_token)
+            .field("sso_token", &self.sso_token)
+            .field("cookies", &cookies)
+            .field("created_at", &self.created_at)
+            .field("last_verified_at", &self.last_verified_at)
+            .field("expires_at", &self.expires_at)
+            .field("checksum", &self.checksum)
+            .finish()
+    }
+}
+
+impl SessionFile {
+    pub fn new(
+        roll_number: Option<String>,
+        session_token: Option<Secret<String>>,
+        sso_token: Secret<String>,
+        cookies: Vec<Cookie<'static>>,
+        created_at: DateTime<Utc>,
+        last_verified_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        let mut session_file = Self {
+            format: SESSION_FILE_FORMAT.into(),
+            version: SESSION_FILE_VERSION,
+            roll_number,
+            session_token,
+            sso_token,
+            cookies,
+            created_at,
+            last_verified_at,
+            expires_at: None,
+            checksum: String::new(),
+        };
+        session_file.expires_at = session_file.estimate_expiry();
+
+        session_file
+    }
+
+    /// Estimates the expiry from the SSO token cookie, or from when the session was last known to be alive
+    fn estimate_expiry(&self) -> Option<DateTime<Utc>> {
+        let cookie_expiry = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.name() == "ssoToken")
+            .find_map(|cookie| match &cookie.expires {
+                CookieExpiration::AtUtc(expires_at) => {
+                    DateTime::from_timestamp(expires_at.unix_timestamp(), 0)
+                }
+                CookieExpiration::SessionEnd => None,
+            });
+
+        cookie_expiry.or_else(|| {
+            Some(self.last_verified_at.unwrap_or(self.created_at) + ESTIMATED_SESSION_LIFETIME)
+        })
+    }
+
+    /// Computes the checksum of the file contents, excluding the checksum itself
+    fn compute_checksum(&self) -> Res<String> {
+        let mut unchecked = self.clone();
+        unchecked.checksum = String::new();
+
+        let digest = Sha256::digest(serde_json::to_vec(&unchecked)?);
+        Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+
+    /// Serializes the session file to JSON, with its checksum
+    pub fn to_json(&self) -> Res<String> {
+        let mut session_file = self.clone();
+        session_file.checksum = self.compute_checksum()?;
+
+        Ok(serde_json::to_string_pretty(&session_file)?)
+    }
+
+    /// Parses a session file, migrating legacy session files.
+    /// `modified_at` is used as the creation time of legacy files.
+    pub fn from_contents(contents: &str, modified_at: DateTime<Utc>) -> Res<Self> {
+        if contents.trim().is_empty() {
+            return Err(ErpError::SessionFile("empty session file".into()));
+        }
+        if !contents.trim_start().starts_with('{') {
+            return Self::from_legacy(contents, modified_at);
+        }
+
+        let value: serde_json::Value = serde_json::from_str(contents)
+            .map_err(|err| ErpError::SessionFile(format!("corrupt session file: {err}")))?;
+
+        if value.get("format").and_then(|format| format.as_str()) != Some(SESSION_FILE_FORMAT) {
+            return Err(ErpError::SessionFile(
+                "not an iitkgp-erp-login session file".into(),
+            ));
+        }
+        match value.get("version").and_then(|version| version.as_u64()) {
+            Some(version) if version == u64::from(SESSION_FILE_VERSION) => {}
+            Some(version) => {
+                return Err(ErpError::SessionFile(format!(
+                    "unsupported session file version {version}"
+                )));
+            }
+            None => return Err(ErpError::SessionFile("session file has no version".into())),
+        }
+
+        let session_file: Self = serde_json::from_value(value)
+            .map_err(|err| ErpError::SessionFile(format!("corrupt session file: {err}")))?;
+        if session_file.checksum != session_file.compute_checksum()? {
+            return Err(ErpError::SessionFile(
+                "checksum mismatch, the session file is corrupt or was modified".into(),
+            ));
+        }
+
+        Ok(session_file)
+    }
+
+    /// Migrates a legacy session file: the session token and SSO token on two lines, optionally followed by the cookies as JSON.
+    /// An empty SSO token line means the session was saved before signing in.
+    fn from_legacy(contents: &str, modified_at: DateTime<Utc>) -> Res<Self> {
+        let invalid = || ErpError::SessionFile("not a session file".into());
+        let is_token = |token: &str| token.chars().all(|c| c.is_ascii_graphic());
+
+        let mut lines = contents
+            .splitn(3, '\n')
+            .map(|line| line.trim_end_matches('\r'));
+        let session_token = lines
+            .next()
+            .filter(|token| is_token(token))
+            .ok_or_else(invalid)?;
+        let sso_token = lines
+            .next()
+            .ok_or(ErpError::SessionFile("truncated session file".into()))?;
+        if sso_token.is_empty() {
+            return Err(ErpError::NotLoggedIn);
+        }
+        if !is_token(sso_token) {
+            return Err(invalid());
+        }
+        let cookies = match lines
+            .next()
+            .map(str::trim)
+            .filter(|cookies| !cookies.is_empty())
+        {
+            Some(cookies) => serde_json::from_str(cookies).map_err(|_| invalid())?,
+            None => Vec::new(),
+        };
+
+        Ok(Self::new(
+            None,
+            Some(session_token)
+                .filter(|token| !token.is_empty())
+                .map(Secret::from),
+            sso_token.into(),
+            cookies,
+            modified_at,
+            None,
+        ))
+    }
+
+    /// Saves the session file, encrypted with the key from `key_source` if given
+    pub async fn save<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        key_source: Option<&KeySource>,
+    ) -> Res<()> {
+        let contents = match key_source {
+            Some(key_source) => {
+                Encrypted::encrypt(self.to_json()?.as_bytes(), key_source)?.to_json()?
+            }
+            None => self.to_json()?,
+        };
+        if let Some(dir) = file_path.as_ref().parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        fs::write(file_path, contents).await?;
+
+        Ok(())
+    }
+
+    /// Reads a session file, decrypting it with the key from `key_source` and migrating legacy session files.
+    /// Reading an encrypted session file without a key fails with [`ErpError::SessionEncrypted`].
+    pub async fn read<P: AsRef<Path>>(file_path: P, key_source: Option<&KeySource>) -> Res<Self> {
+        let mut contents = fs::read(&file_path).await?;
+        if let Some(encrypted) = Encrypted::from_contents(&contents)? {
+            let key_source = key_source.ok_or(ErpError::SessionEncrypted)?;
+            contents = encrypted.decrypt(key_source)?;
+        }
+        let contents = String::from_utf8(contents)
+            .map_err(|_| ErpError::SessionFile("not a session file".into()))?;
+        let modified_at = fs::metadata(&file_path)
+            .await?
+            .modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+
+        Self::from_contents(&contents, modified_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::*;
+
+    fn cookie(cookie: &str) -> Cookie<'static> {
+        let url = Url::parse("https://erp.iitkgp.ac.in/").unwrap();
+        Cookie::parse(cookie, &url).unwrap().into_owned()
+    }
+
+    fn session_file() -> SessionFile {
+        SessionFile::new(
+            Some("21CS10001".into()),
+            Some("session-token".to_owned().into()),
+            "sso-token".to_owned().into(),
+            vec![
+                cookie("ssoToken=sso-token; Path=/"),
+                cookie("JSESSIONID=jsessionid; Path=/"),
+            ],
+            Utc::now(),
+            None,
+        )
+    }
+
+    fn cookie_pairs(session_file: &SessionFile) -> Vec<(String, String)> {
+        session_file
+            .cookies
+            .iter()
+            .map(|cookie| (cookie.name().to_owned(), cookie.value().to_owned()))
+            .collect()
+    }
+
+    fn is_session_file_error<T>(result: Res<T>) -> bool {
+        matches!(result, Err(ErpError::SessionFile(_)))
+    }
+
+    #[test]
+    fn migrates_legacy_file() {
+        let modified_at = Utc::now();
+        let session_file =
+            SessionFile::from_contents("session-token\nsso-token\n", modified_at).unwrap();
+
+        assert_eq!(session_file.version, SESSION_FILE_VERSION);
+        assert_eq!(
+            session_file
+                .session_token
+                .as_ref()
+                .map(|token| token.expose().as_str()),
+            Some("session-token")
+        );
+        assert_eq!(session_file.sso_token.expose(), "sso-token");
+        assert!(session_file.cookies.is_empty());
+        assert_eq!(session_file.created_at, modified_at);
+    }
+
+    #[test]
+    fn migrates_legacy_file_with_cookies() {
+        let cookies = vec![cookie("ssoToken=sso-token; Path=/")];
+        let contents = format!(
+            "session-token\r\nsso-token\r\n{}\n",
+            serde_json::to_string(&cookies).unwrap()
+        );
+        let session_file = SessionFile::from_contents(&contents, Utc::now()).unwrap();
+
+        assert_eq!(session_file.sso_token.expose(), "sso-token");
+        assert_eq!(
+            cookie_pairs(&session_file),
+            [("ssoToken".to_owned(), "sso-token".to_owned())]
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_truncated_files() {
+        assert!(is_session_file_error(SessionFile::from_contents(
+            "",
+            Utc::now()
+        )));
+        assert!(is_session_file_error(SessionFile::from_contents(
+            " \n",
+            Utc::now()
+        )));
+        assert!(is_session_file_error(SessionFile::from_contents(
+            "session-token",
+            Utc::now()
+        )));
+        assert!(matches!(
+            SessionFile::from_contents("session-token\n", Utc::now()),
+            Err(ErpError::NotLoggedIn)
+        ));
+    }
+
+    #[test]
+    fn rejects_foreign_json() {
+        let result = SessionFile::from_contents(r#"{"name": "package", "version": 1}"#, Utc::now());
+        assert!(is_session_file_error(result));
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let mut value: serde_json::Value =
+            serde_json::from_str(&session_file().to_json().unwrap()).unwrap();
+        value["version"] = (SESSION_FILE_VERSION + 1).into();
+
+        let result = SessionFile::from_contents(&value.to_string(), Utc::now());
+        assert!(
+            matches!(result, Err(ErpError::SessionFile(message)) if message.contains("unsupported session file version"))
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_file() {
+        let mut value: serde_json::Value =
+            serde_json::from_str(&session_file().to_json().unwrap()).unwrap();
+        value["roll_number"] = "21CS10002".into();
+
+        let result = SessionFile::from_contents(&value.to_string(), Utc::now());
+        assert!(
+            matches!(result, Err(ErpError::SessionFile(message)) if message.contains("checksum mismatch"))
+        );
+    }
+
+    #[test]
+    fn reads_saved_file() {
+        let saved = session_file();
+        let read = SessionFile::from_contents(&saved.to_json().unwrap(), Utc::now()).unwrap();
+
+        assert_eq!(read.roll_number, saved.roll_number);
+        assert_eq!(read.sso_token.expose(), saved.sso_token.expose());
+        assert_eq!(read.created_at, saved.created_at);
+        assert_eq!(cookie_pairs(&read), cookie_pairs(&saved));
+    }
+}
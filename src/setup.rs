@@ -0,0 +1,55 @@
+use crate::{
+    answer::StoredAnswers,
+    erp::Endpoints,
+    session::Session,
+    utils::{ErpCreds, Res},
+    verify,
+};
+
+/// Number of security questions ERP has for each account
+pub const SECURITY_QUESTION_COUNT: usize = 3;
+/// Default number of sessions to open while discovering the security questions
+pub const DISCOVERY_TRIES: usize = 20;
+
+/// Discovers the security questions of an account. ERP asks one of them at random for each session, so new sessions
+/// are opened until `count` distinct questions were seen or `tries` sessions were opened.
+/// Returns the questions in the order they were first asked, which may be fewer than `count`.
+pub async fn discover_questions(
+    roll_number: &str,
+    endpoints: &Endpoints,
+    count: usize,
+    tries: usize,
+) -> Res<Vec<String>> {
+    let credentials = ErpCreds {
+        roll_number: Some(roll_number.into()),
+        ..ErpCreds::default()
+    };
+
+    let mut questions: Vec<String> = Vec::new();
+    for _ in 0..tries {
+        if questions.len() >= count {
+            break;
+        }
+
+        let session = Session::with_endpoints(credentials.clone(), None, endpoints.clone())
+            .get_secret_question(None)
+            .await?;
+        if !questions
+            .iter()
+            .any(|question| question == session.question())
+        {
+            questions.push(session.question().to_owned());
+        }
+    }
+
+    Ok(questions)
+}
+
+/// Checks the password and the answer to one security question by requesting an OTP for a new session
+/// (see [`verify`](crate::verify::verify)). ERP emails an OTP if they are correct.
+/// Returns the security question that was checked.
+pub async fn verify_with_otp(credentials: &ErpCreds, endpoints: &Endpoints) -> Res<String> {
+    let verification = verify::verify(credentials, endpoints, &StoredAnswers, true).await?;
+
+    Ok(verification.question)
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+use reqwest::{Client, Response, ResponseBuilderExt, StatusCode, Url};
+use scraper::{Html, Selector};
+
+use crate::{erp::Endpoints, error::ErpError, utils::Res};
+
+/// Liveness of a session, as decided by [`Session::status`](crate::Session::status)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// ERP served a page only available to a logged in session
+    Alive(Evidence),
+    /// ERP asked the session to log in even though it has an SSO token
+    Expired(Evidence),
+    /// ERP asked the session to log in and it has no SSO token
+    NotLoggedIn(Evidence),
+    /// ERP could not be reached or responded with an error
+    ErpUnavailable(Evidence),
+}
+
+/// What a [`SessionStatus`] was decided from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evidence {
+    /// URL the welcome page request ended at, after redirects
+    pub url: Option<String>,
+    /// HTTP status code of the response
+    pub status: Option<u16>,
+    /// Why the status was decided
+    pub reason: String,
+}
+
+impl SessionStatus {
+    pub fn is_alive(&self) -> bool {
+        matches!(self, Self::Alive(_))
+    }
+
+    /// Returns the evidence the status was decided from
+    pub fn evidence(&self) -> &Evidence {
+        match self {
+            Self::Alive(evidence)
+            | Self::Expired(evidence)
+            | Self::NotLoggedIn(evidence)
+            | Self::ErpUnavailable(evidence) => evidence,
+        }
+    }
+}
+
+impl fmt::Display for SessionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Self::Alive(_) => "alive",
+            Self::Expired(_) => "expired",
+            Self::NotLoggedIn(_) => "not logged in",
+            Self::ErpUnavailable(_) => "ERP unavailable",
+        };
+
+        write!(f, "{status} ({})", self.evidence().reason)
+    }
+}
+
+/// Decides the status of a session from the page ERP serves for the welcome page.
+/// The welcome page is only accessible when NOT logged in, so a logged in session is either redirected away from it
+/// or served a page without the login form.
+pub(crate) async fn check(
+    client: &Client,
+    endpoints: &Endpoints,
+    has_sso_token: bool,
+) -> SessionStatus {
+    let resp = match client.get(&endpoints.welcome_page).send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            return SessionStatus::ErpUnavailable(Evidence {
+                url: err.url().map(|url| url.to_string()),
+                status: err.status().map(|status| status.as_u16()),
+                reason: format!("request failed: {err}"),
+            });
+        }
+    };
+
+    let url = resp.url().to_owned();
+    let status = resp.status();
+    let evidence = |reason: &str| Evidence {
+        url: Some(url.to_string()),
+        status: Some(status.as_u16()),
+        reason: reason.into(),
+    };
+
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        return SessionStatus::ErpUnavailable(evidence("ERP responded with an error"));
+    }
+
+    let login_required = if is_sso_url(&url) {
+        Some("redirected to the SSO login")
+    } else {
+        match resp.text().await {
+            Ok(page) if has_login_form(&page) => Some("served the login form"),
+            Ok(_) if status.is_success() => None,
+            Ok(_) => {
+                return SessionStatus::ErpUnavailable(evidence("ERP responded with an error"));
+            }
+            Err(_) => {
+                return SessionStatus::ErpUnavailable(evidence("response body could not be read"));
+            }
+        }
+    };
+
+    match login_required {
+        Some(reason) if has_sso_token => SessionStatus::Expired(evidence(reason)),
+        Some(reason) => SessionStatus::NotLoggedIn(evidence(reason)),
+        None => SessionStatus::Alive(evidence("served a page without the login form")),
+    }
+}
+
+/// Checks if a URL is on the SSO administration, where ERP sends sessions that need to log in
+fn is_sso_url(url: &Url) -> bool {
+    url.path().contains("/SSOAdministration/")
+}
+
+/// Checks if a URL is on the welcome page, which is served with the login form to sessions that need to log in
+fn is_welcome_page_url(url: &Url, endpoints: &Endpoints) -> bool {
+    Url::parse(&endpoints.welcome_page).is_ok_and(|welcome_page| welcome_page.path() == url.path())
+}
+
+/// Checks that a response did not end on a page ERP serves to sessions that are not logged in: the SSO login,
+/// or the welcome page with the login form (as in [`check`]). Returns the response, with its body read again
+/// if it ended on the welcome page, or fails with [`ErpError::LoggedOut`].
+pub(crate) async fn ensure_logged_in(resp: Response, endpoints: &Endpoints) -> Res<Response> {
+    let url = resp.url().to_owned();
+    let logged_out = || {
+        // The query may carry the SSO token
+        let mut url = url.clone();
+        url.set_query(None);
+        url.set_fragment(None);
+
+        ErpError::LoggedOut {
+            url: url.to_string(),
+        }
+    };
+
+    if is_sso_url(&url) {
+        return Err(logged_out());
+    }
+    if !is_welcome_page_url(&url, endpoints) {
+        return Ok(resp);
+    }
+
+    let mut builder = http::Response::builder()
+        .status(resp.status())
+        .version(resp.version())
+        .url(url.clone());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = resp.headers().clone();
+    }
+    let page = resp.bytes().await.map_err(reqwest::Error::without_url)?;
+
+    if has_login_form(&String::from_utf8_lossy(&page)) {
+        return Err(logged_out());
+    }
+
+    Ok(builder
+        .body(page)
+        .map_err(|err| ErpError::Parse(err.to_string()))?
+        .into())
+}
+
+/// Checks if a page is the ERP login page
+fn has_login_form(page: &str) -> bool {
+    let document = Html::parse_document(page);
+    let login_form_selector = Selector::parse("#sessionToken, input[name=user_id]")
+        .expect("Error parsing login form selector.");
+
+    document.select(&login_form_selector).next().is_some()
+}
@@ -1,64 +1,127 @@
-use std::{
-    collections::HashMap,
-    error::Error,
-    path::{Path, PathBuf},
-};
-
-use tokio::fs;
+use std::{collections::HashMap, env, fs, path::Path};
 
 use serde::{Deserialize, Serialize};
-pub type Res<T> = Result<T, Box<dyn Error>>;
-
-/// Saves the session token and SSO token on a file
-pub async fn save_session_file(
-    file_path: PathBuf,
-    session_token: Option<&str>,
-    sso_token: Option<&str>,
-) -> Res<()> {
-    fs::write(
-        file_path,
-        format!(
-            "{}\n{}\n",
-            session_token.unwrap_or_default(),
-            sso_token.unwrap_or_default()
-        ),
-    )
-    .await?;
-
-    Ok(())
-}
 
-/// Reads a session file and returns the session token and SSO token (if they exist)
-pub async fn read_session_file(file_path: PathBuf) -> Res<(Option<String>, Option<String>)> {
-    let file_contents = fs::read_to_string(file_path).await?;
-    let mut lines = file_contents.lines();
+use crate::{
+    answer::answers_from_env,
+    creds::{PASSWORD_ENV_VAR, ROLL_NUMBER_ENV_VAR},
+    crypto::{Encrypted, KeySource},
+    error::ErpError,
+    secret::Secret,
+};
 
-    Ok((
-        lines.next().map(str::to_string),
-        lines.next().map(str::to_string),
-    ))
-}
+pub type Res<T> = Result<T, ErpError>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 /// Used to store ERP credentials in a file (typically erpcreds.json)
 pub struct ErpCreds {
     /// Student Roll Number
     pub roll_number: Option<String>,
     /// ERP Password
-    pub password: Option<String>,
+    pub password: Option<Secret<String>>,
     /// Security Question
-    pub answer_map: Option<HashMap<String, String>>,
+    pub answer_map: Option<HashMap<String, Secret<String>>>,
 }
 
 impl ErpCreds {
+    /// Reads the credentials from the environment: the roll number from `ERP_ROLL_NUMBER`, the password from
+    /// `ERP_PASSWORD` and the security question answers as read by [`answers_from_env`].
+    /// Unset or empty variables leave the credential missing.
+    pub fn from_env() -> Res<Self> {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        let answer_map = answers_from_env()?;
+
+        Ok(Self {
+            roll_number: var(ROLL_NUMBER_ENV_VAR),
+            password: var(PASSWORD_ENV_VAR).map(Secret::new),
+            answer_map: Some(answer_map).filter(|answer_map| !answer_map.is_empty()),
+        })
+    }
+
+    /// Checks if the roll number, password and security question answers are all set
+    pub fn is_complete(&self) -> bool {
+        self.roll_number.is_some() && self.password.is_some() && self.answer_map.is_some()
+    }
+
+    /// Fills the credentials missing in `self` from `other`. The answer maps are merged, `self` taking precedence.
+    pub fn merge(self, other: Self) -> Self {
+        let answer_map = match (self.answer_map, other.answer_map) {
+            (Some(answer_map), Some(mut other_answer_map)) => {
+                other_answer_map.extend(answer_map);
+                Some(other_answer_map)
+            }
+            (answer_map, other_answer_map) => answer_map.or(other_answer_map),
+        };
+
+        Self {
+            roll_number: self.roll_number.or(other.roll_number),
+            password: self.password.or(other.password),
+            answer_map,
+        }
+    }
+
+    /// Reads a plaintext or encrypted credentials file.
+    /// The passphrase of an encrypted file is read with [`crypto::read_passphrase`](crate::crypto::read_passphrase).
     pub fn from_file<P: AsRef<Path>>(file_path: P) -> Res<Self> {
-        let file_reader = std::fs::File::open(file_path)?;
+        let contents = fs::read(&file_path)?;
 
-        Ok(serde_json::from_reader(file_reader)?)
+        match Encrypted::from_contents(&contents)? {
+            Some(encrypted) => {
+                let key_source = KeySource::passphrase(&format!(
+                    "Enter passphrase for {}: ",
+                    file_path.as_ref().display()
+                ))?;
+
+                Ok(serde_json::from_slice(&encrypted.decrypt(&key_source)?)?)
+            }
+            None => Ok(serde_json::from_slice(&contents)?),
+        }
+    }
+
+    /// Reads a plaintext or encrypted credentials file, decrypting it with the key from `key_source`
+    pub fn from_file_with_key<P: AsRef<Path>>(file_path: P, key_source: &KeySource) -> Res<Self> {
+        let contents = fs::read(file_path)?;
+
+        match Encrypted::from_contents(&contents)? {
+            Some(encrypted) => Ok(serde_json::from_slice(&encrypted.decrypt(key_source)?)?),
+            None => Ok(serde_json::from_slice(&contents)?),
+        }
     }
 
     pub fn save_to_file<P: AsRef<Path>>(&self, file_path: P) -> Res<()> {
-        let file_writer = std::fs::File::create(file_path)?;
+        let file_writer = fs::File::create(file_path)?;
         Ok(serde_json::to_writer(file_writer, self)?)
     }
+
+    /// Saves the credentials encrypted with the key from `key_source`
+    pub fn save_encrypted<P: AsRef<Path>>(&self, file_path: P, key_source: &KeySource) -> Res<()> {
+        let encrypted = Encrypted::encrypt(&serde_json::to_vec(self)?, key_source)?;
+
+        Ok(fs::write(file_path, encrypted.to_json()?)?)
+    }
+
+    /// Encrypts a plaintext credentials file in place with the key from `key_source`
+    pub fn encrypt_file<P: AsRef<Path>>(file_path: P, key_source: &KeySource) -> Res<()> {
+        let file_path = file_path.as_ref();
+        let contents = fs::read(file_path)?;
+        if Encrypted::from_contents(&contents)?.is_some() {
+            return Err(ErpError::Encryption(format!(
+                "{} is already encrypted",
+                file_path.display()
+            )));
+        }
+
+        let creds: Self = serde_json::from_slice(&contents)?;
+
+        // Write to a temporary file first so the plaintext is only replaced once encryption succeeded
+        let mut tmp_path = file_path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        creds.save_encrypted(&tmp_path, key_source)?;
+
+        Ok(fs::rename(tmp_path, file_path)?)
+    }
 }
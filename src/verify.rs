@@ -0,0 +1,206 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use chrono::{Datelike, Utc};
+
+use crate::{
+    answer::AnswerProvider,
+    erp::Endpoints,
+    error::ErpError,
+    session::{QuestionFetched, Session},
+    utils::{ErpCreds, Res},
+};
+
+/// A roll number split into its parts, e.g. `21CS10001`: year `21`, department `CS`, serial `10001`.
+/// Research scholars' serials contain letters, e.g. `19CS91R01`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollNumber {
+    /// Last two digits of the year of admission
+    pub year: String,
+    /// Department code
+    pub department: String,
+    /// Serial number, starting with the programme digit, e.g. `10001` or `91R01`
+    pub serial: String,
+}
+
+/// The part of a roll number that is malformed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollNumberPart {
+    /// The roll number is not 9 characters long
+    Length,
+    /// The year is not two digits, or is in the future
+    Year,
+    /// The department code is not two letters
+    Department,
+    /// The serial is not five letters or digits
+    Serial,
+}
+
+impl fmt::Display for RollNumberPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::Length => "a roll number has 9 characters, like 21CS10001",
+            Self::Year => "the year must be two digits and not in the future",
+            Self::Department => "the department code must be two letters",
+            Self::Serial => "the serial must be five letters or digits",
+        };
+
+        f.write_str(reason)
+    }
+}
+
+impl FromStr for RollNumber {
+    type Err = ErpError;
+
+    /// Checks the format of a roll number locally, ignoring case and surrounding whitespace
+    fn from_str(roll_number: &str) -> Res<Self> {
+        let normalized = roll_number.trim().to_ascii_uppercase();
+        let invalid = |part| ErpError::InvalidRollNumberFormat {
+            roll_number: roll_number.into(),
+            part,
+        };
+
+        if normalized.len() != 9 || !normalized.is_ascii() {
+            return Err(invalid(RollNumberPart::Length));
+        }
+        let (year, rest) = normalized.split_at(2);
+        let (department, serial) = rest.split_at(2);
+
+        let current_year = (Utc::now().year() % 100) as u8;
+        if !year.bytes().all(|c| c.is_ascii_digit())
+            || year.parse::<u8>().is_ok_and(|year| year > current_year)
+        {
+            return Err(invalid(RollNumberPart::Year));
+        }
+        if !department.bytes().all(|c| c.is_ascii_alphabetic()) {
+            return Err(invalid(RollNumberPart::Department));
+        }
+        if !serial.bytes().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid(RollNumberPart::Serial));
+        }
+
+        Ok(Self {
+            year: year.into(),
+            department: department.into(),
+            serial: serial.into(),
+        })
+    }
+}
+
+impl fmt::Display for RollNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.year, self.department, self.serial)
+    }
+}
+
+/// Result of [`verify`]
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub roll_number: RollNumber,
+    /// The security question ERP asked, which has an answer
+    pub question: String,
+    /// Whether ERP accepted the password and the answer and sent an OTP
+    pub otp_requested: bool,
+}
+
+/// Checks the roll number's format locally, then confirms ERP knows it by fetching a security question.
+/// Fails with [`ErpError::InvalidRollNumberFormat`] or [`ErpError::InvalidRollNumber`].
+pub async fn check_roll_number(
+    credentials: &ErpCreds,
+    endpoints: &Endpoints,
+) -> Res<(RollNumber, Session<QuestionFetched>)> {
+    let roll_number: RollNumber = credentials
+        .roll_number
+        .as_deref()
+        .ok_or(ErpError::Missing("roll number"))?
+        .parse()?;
+
+    let credentials = ErpCreds {
+        roll_number: Some(roll_number.to_string()),
+        ..credentials.clone()
+    };
+    let session = Session::with_endpoints(credentials, None, endpoints.clone())
+        .get_secret_question(None)
+        .await?;
+
+    Ok((roll_number, session))
+}
+
+/// Validates credentials without signing in, failing with an error naming the wrong credential:
+/// 1. the roll number's format and whether ERP knows it (see [`check_roll_number`])
+/// 2. whether `answers` has an answer to the security question ERP asks, and the password is set
+/// 3. if `request_otp` is set, whether ERP accepts the password and the answer, by requesting an OTP
+///    ([`ErpError::PasswordMismatch`] or [`ErpError::AnswerMismatch`]). ERP emails the OTP, which is not used.
+pub async fn verify<A: AnswerProvider>(
+    credentials: &ErpCreds,
+    endpoints: &Endpoints,
+    answers: &A,
+    request_otp: bool,
+) -> Res<Verification> {
+    let (roll_number, session) = check_roll_number(credentials, endpoints).await?;
+    let question = session.question().to_owned();
+
+    let answer = answers.answer(&question, credentials).await?;
+    if credentials.password.is_none() {
+        return Err(ErpError::Missing("password"));
+    }
+
+    if request_otp {
+        // Answers with the answer found above, so it is not looked up or asked for again
+        let answer = HashMap::from([(question.clone(), answer)]);
+        session.request_otp(None, &answer).await?;
+    }
+
+    Ok(Verification {
+        roll_number,
+        question,
+        otp_requested: request_otp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_part(roll_number: &str) -> Option<RollNumberPart> {
+        match roll_number.parse::<RollNumber>() {
+            Err(ErpError::InvalidRollNumberFormat { part, .. }) => Some(part),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_roll_numbers() {
+        let roll_number: RollNumber = " 21cs10001\n".parse().unwrap();
+        assert_eq!(roll_number.year, "21");
+        assert_eq!(roll_number.department, "CS");
+        assert_eq!(roll_number.serial, "10001");
+        assert_eq!(roll_number.to_string(), "21CS10001");
+    }
+
+    #[test]
+    fn parses_research_scholar_roll_numbers() {
+        for roll_number in ["19CS91R01", "20MA92P05", "20ma92p05"] {
+            let parsed: RollNumber = roll_number.parse().unwrap();
+            assert_eq!(parsed.to_string(), roll_number.to_ascii_uppercase());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_parts() {
+        let next_year = (Utc::now().year() % 100 + 1) % 100;
+
+        assert_eq!(invalid_part("21CS1000"), Some(RollNumberPart::Length));
+        assert_eq!(invalid_part("21CS100011"), Some(RollNumberPart::Length));
+        assert_eq!(invalid_part("21CS1000\u{e9}"), Some(RollNumberPart::Length));
+        assert_eq!(invalid_part("2ACS10001"), Some(RollNumberPart::Year));
+        if next_year != 0 {
+            assert_eq!(
+                invalid_part(&format!("{next_year:02}CS10001")),
+                Some(RollNumberPart::Year)
+            );
+        }
+        assert_eq!(invalid_part("21C510001"), Some(RollNumberPart::Department));
+        assert_eq!(invalid_part("21CS1000-"), Some(RollNumberPart::Serial));
+        assert_eq!(invalid_part("21CS91 01"), Some(RollNumberPart::Serial));
+    }
+}
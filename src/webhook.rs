@@ -0,0 +1,291 @@
+//! A local HTTP endpoint that receives OTPs forwarded from a phone, e.g. by an SMS or email forwarder app,
+//! Tasker or iOS Shortcuts.
+//!
+//! Forward the OTP or the whole email subject with a `POST` to [`WebhookRetriever::url`], authenticated with the
+//! shared token as `Authorization: Bearer <token>` (or a `token` query parameter for apps that cannot set headers).
+//! The body is JSON or a form with an `otp` or `subject` field, or plain text. The endpoint is plain HTTP:
+//! only expose it on a trusted network.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    routing::post,
+};
+use sha2::{Digest, Sha256};
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    sync::watch,
+    task::JoinHandle,
+    time::timeout,
+};
+
+use crate::{
+    error::ErpError,
+    otp::{OTPRetriever, get_otp_from_sub},
+    secret::Secret,
+    utils::Res,
+};
+
+/// Path the OTP is posted to
+pub const WEBHOOK_PATH: &str = "/otp";
+/// Default time [`WebhookRetriever::get_otp`](OTPRetriever::get_otp) waits for an OTP
+pub const DEFAULT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// An OTP received by the webhook
+#[derive(Debug, Clone)]
+struct Received {
+    otp: String,
+    received_at: i64,
+}
+
+struct WebhookState {
+    token: Secret<String>,
+    latest: watch::Sender<Option<Received>>,
+}
+
+/// Retrieves the OTP from a local HTTP endpoint the OTP is forwarded to (see the [module docs](self)).
+/// [`get_otp`](OTPRetriever::get_otp) resolves as soon as an OTP arrives, if it arrived after `after_timestamp`.
+/// The server shuts down when this is dropped.
+pub struct WebhookRetriever {
+    addr: SocketAddr,
+    latest: watch::Receiver<Option<Received>>,
+    timeout: Duration,
+    handle: JoinHandle<()>,
+}
+
+impl WebhookRetriever {
+    /// Starts the endpoint on `addr`, accepting requests carrying `token`. Use port 0 for a random port.
+    /// Fails with [`ErpError::Missing`] if `token` is empty.
+    pub async fn bind<A: ToSocketAddrs>(addr: A, token: impl Into<Secret<String>>) -> Res<Self> {
+        let token = token.into();
+        if token.expose().is_empty() {
+            return Err(ErpError::Missing("webhook token"));
+        }
+
+        let (latest_tx, latest) = watch::channel(None);
+        let state = Arc::new(WebhookState {
+            token,
+            latest: latest_tx,
+        });
+
+        let app = Router::new()
+            .route(WEBHOOK_PATH, post(receive_otp))
+            .with_state(state);
+
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Self {
+            addr,
+            latest,
+            timeout: DEFAULT_WEBHOOK_TIMEOUT,
+            handle,
+        })
+    }
+
+    /// Time to wait for an OTP before giving up. Defaults to [`DEFAULT_WEBHOOK_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the address the endpoint is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the URL to post the OTP to, e.g. `http://127.0.0.1:8080/otp`
+    pub fn url(&self) -> String {
+        format!("http://{}{WEBHOOK_PATH}", self.addr)
+    }
+}
+
+impl Drop for WebhookRetriever {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl OTPRetriever for WebhookRetriever {
+    /// Returns the newest OTP received after `after_timestamp`, waiting for one up to the timeout
+    async fn get_otp(&self, after_timestamp: i64) -> Res<Option<String>> {
+        let mut latest = self.latest.clone();
+        let received = timeout(
+            self.timeout,
+            latest.wait_for(|received| {
+                received
+                    .as_ref()
+                    .is_some_and(|received| received.received_at >= after_timestamp)
+            }),
+        )
+        .await;
+
+        Ok(match received {
+            Ok(Ok(received)) => received.as_ref().map(|received| received.otp.clone()),
+            // Timed out, or the server stopped
+            _ => None,
+        })
+    }
+
+    /// Waits for the OTP once, up to the timeout: the OTP is pushed to the endpoint, so there is nothing to poll
+    async fn wait_for_otp(&self, after_timestamp: i64, _tries: usize) -> Res<Option<String>> {
+        self.get_otp(after_timestamp).await
+    }
+}
+
+/// Compares the tokens' digests in constant time, so neither the token nor its length can be guessed
+/// from response times
+fn token_matches(token: &str, expected: &str) -> bool {
+    Sha256::digest(token)
+        .iter()
+        .zip(Sha256::digest(expected).iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Extracts the OTP from a JSON, form or plain text body, with an `otp` or a `subject` field
+fn parse_body(headers: &HeaderMap, body: &[u8]) -> Option<String> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let fields: HashMap<String, String> = if content_type.starts_with("application/json") {
+        serde_json::from_slice::<HashMap<String, serde_json::Value>>(body)
+            .ok()?
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(value) => (key, value),
+                value => (key, value.to_string()),
+            })
+            .collect()
+    } else if content_type.starts_with("application/x-www-form-urlencoded") {
+        serde_urlencoded::from_bytes(body).ok()?
+    } else {
+        HashMap::from([("subject".into(), String::from_utf8_lossy(body).into_owned())])
+    };
+
+    ["otp", "subject"]
+        .iter()
+        .filter_map(|field| fields.get(*field))
+        .find_map(|text| get_otp_from_sub(text))
+}
+
+async fn receive_otp(
+    State(state): State<Arc<WebhookState>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, &'static str) {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(query.get("token").map(String::as_str));
+    if !token.is_some_and(|token| token_matches(token, state.token.expose())) {
+        return (StatusCode::UNAUTHORIZED, "invalid token\n");
+    }
+
+    let Some(otp) = parse_body(&headers, &body) else {
+        return (StatusCode::BAD_REQUEST, "no OTP found\n");
+    };
+
+    state.latest.send_replace(Some(Received {
+        otp,
+        received_at: chrono::Local::now().timestamp(),
+    }));
+
+    (StatusCode::OK, "OTP received\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "shared-token";
+
+    async fn retriever() -> WebhookRetriever {
+        WebhookRetriever::bind("127.0.0.1:0", TOKEN.to_owned())
+            .await
+            .unwrap()
+            .timeout(Duration::from_millis(200))
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_token() {
+        let result = WebhookRetriever::bind("127.0.0.1:0", String::new()).await;
+        assert!(matches!(result, Err(ErpError::Missing(_))));
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(token_matches(TOKEN, TOKEN));
+        assert!(!token_matches("shared-tokem", TOKEN));
+        assert!(!token_matches("shared", TOKEN));
+        assert!(!token_matches("", TOKEN));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_token() {
+        let webhook = retriever().await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(webhook.url())
+            .bearer_auth("wrong-token")
+            .body("123456")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .post(webhook.url())
+            .body("123456")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(webhook.get_otp(0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_body_without_otp() {
+        let webhook = retriever().await;
+        let response = reqwest::Client::new()
+            .post(webhook.url())
+            .bearer_auth(TOKEN)
+            .body("no code here")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(webhook.get_otp(0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn accepts_otp() {
+        let webhook = retriever().await;
+        let after = chrono::Local::now().timestamp();
+        let response = reqwest::Client::new()
+            .post(format!("{}?token={TOKEN}", webhook.url()))
+            .body("Your ERP OTP is 123456")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            webhook.get_otp(after).await.unwrap().as_deref(),
+            Some("123456")
+        );
+    }
+}